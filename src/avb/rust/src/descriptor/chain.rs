@@ -15,11 +15,15 @@
 //! Chain partition descriptors.
 
 use super::{
-    util::{parse_descriptor, split_slice, ValidateAndByteswap, ValidationFunc},
+    util::{
+        check_descriptor_tag, parse_descriptor, split_slice, validate_body_lengths,
+        ValidateAndByteswap, ValidationFunc,
+    },
     DescriptorResult,
 };
 use avb_bindgen::{
     avb_chain_partition_descriptor_validate_and_byteswap, AvbChainPartitionDescriptor,
+    AvbDescriptorTag,
 };
 use core::str::from_utf8;
 
@@ -64,8 +68,19 @@ impl<'a> ChainPartitionDescriptor<'a> {
     /// The new descriptor, or `DescriptorError` if the given `contents` aren't a valid
     /// `AvbChainPartitionDescriptor`.
     pub(super) fn new(contents: &'a [u8]) -> DescriptorResult<Self> {
+        check_descriptor_tag(
+            contents,
+            AvbDescriptorTag::AVB_DESCRIPTOR_TAG_CHAIN_PARTITION as u64,
+        )?;
         // Descriptor contains: header + partition name + public key.
         let descriptor = parse_descriptor::<AvbChainPartitionDescriptor>(contents)?;
+        validate_body_lengths(
+            descriptor.body,
+            &[
+                descriptor.header.partition_name_len.into(),
+                descriptor.header.public_key_len.into(),
+            ],
+        )?;
         let (partition_name, remainder) =
             split_slice(descriptor.body, descriptor.header.partition_name_len)?;
         let (public_key, _) = split_slice(remainder, descriptor.header.public_key_len)?;
@@ -15,11 +15,15 @@
 //! Kernel commandline descriptors.
 
 use super::{
-    util::{parse_descriptor, split_slice, ValidateAndByteswap, ValidationFunc},
+    util::{
+        check_descriptor_tag, parse_descriptor, split_slice, validate_body_lengths,
+        ValidateAndByteswap, ValidationFunc,
+    },
     DescriptorResult,
 };
 use avb_bindgen::{
-    avb_kernel_cmdline_descriptor_validate_and_byteswap, AvbKernelCmdlineDescriptor,
+    avb_kernel_cmdline_descriptor_validate_and_byteswap, AvbDescriptorTag,
+    AvbKernelCmdlineDescriptor,
 };
 use core::str::from_utf8;
 
@@ -52,8 +56,16 @@ impl<'a> KernelCommandlineDescriptor<'a> {
     /// The new descriptor, or `DescriptorError` if the given `contents` aren't a valid
     /// `AvbKernelCmdlineDescriptor`.
     pub(super) fn new(contents: &'a [u8]) -> DescriptorResult<Self> {
+        check_descriptor_tag(
+            contents,
+            AvbDescriptorTag::AVB_DESCRIPTOR_TAG_KERNEL_CMDLINE as u64,
+        )?;
         // Descriptor contains: header + commandline.
         let descriptor = parse_descriptor::<AvbKernelCmdlineDescriptor>(contents)?;
+        validate_body_lengths(
+            descriptor.body,
+            &[descriptor.header.kernel_cmdline_length.into()],
+        )?;
         let (commandline, _) =
             split_slice(descriptor.body, descriptor.header.kernel_cmdline_length)?;
 
@@ -0,0 +1,216 @@
+// Copyright 2026, The Android Open Source Project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Descriptor text dumping.
+//!
+//! Renders parsed descriptors in the same textual layout that `avbtool info_image` uses so
+//! the output can be diffed directly against upstream tooling.
+
+use super::Descriptor;
+use core::fmt::{self, Write};
+
+/// Writes the descriptor to `out` in `avbtool info_image` format.
+///
+/// The property rendering matches avbtool exactly; the other descriptor types follow
+/// avbtool's field names and indentation but omit fields which would require hashing
+/// (e.g. the chain partition public key digest).
+///
+/// # Arguments
+/// * `descriptor`: the descriptor to render.
+/// * `out`: destination for the rendered text.
+///
+/// # Returns
+/// `fmt::Error` only if writing to `out` fails.
+pub fn dump_descriptor(descriptor: &Descriptor, out: &mut impl Write) -> fmt::Result {
+    dump_descriptor_impl(descriptor, out, false)
+}
+
+/// Writes the descriptor to `out` like `dump_descriptor()`, but with property values
+/// replaced by `<redacted N bytes>`.
+///
+/// Property values can be device-identifying, so this is the mode to use for dumps that
+/// are going to be shared publicly (e.g. pasted into bug reports): keys and value lengths
+/// remain visible for debugging while the value bytes never appear. Non-property
+/// descriptors carry no free-form values and render identically in both modes.
+///
+/// # Arguments
+/// * `descriptor`: the descriptor to render.
+/// * `out`: destination for the rendered text.
+///
+/// # Returns
+/// `fmt::Error` only if writing to `out` fails.
+pub fn dump_descriptor_redacted(descriptor: &Descriptor, out: &mut impl Write) -> fmt::Result {
+    dump_descriptor_impl(descriptor, out, true)
+}
+
+fn dump_descriptor_impl(
+    descriptor: &Descriptor,
+    out: &mut impl Write,
+    redact: bool,
+) -> fmt::Result {
+    match descriptor {
+        Descriptor::Property(p) => {
+            // avbtool prints printable values quoted and falls back to a byte count for
+            // binary values.
+            let value = &p.value_with_nul[..p.value_with_nul.len() - 1];
+            if redact {
+                return writeln!(
+                    out,
+                    "    Prop: {} -> <redacted {} bytes>",
+                    p.key,
+                    value.len()
+                );
+            }
+            match core::str::from_utf8(value) {
+                Ok(text) if is_printable(text) => {
+                    writeln!(out, "    Prop: {} -> '{}'", p.key, text)
+                }
+                _ => writeln!(out, "    Prop: {} -> ({} bytes)", p.key, value.len()),
+            }
+        }
+        Descriptor::Hash(h) => {
+            writeln!(out, "    Hash descriptor:")?;
+            writeln!(out, "      Image Size:            {} bytes", h.image_size)?;
+            writeln!(out, "      Hash Algorithm:        {}", h.hash_algorithm)?;
+            writeln!(out, "      Partition Name:        {}", h.partition_name)?;
+            write!(out, "      Salt:                  ")?;
+            write_hex(out, h.salt)?;
+            write!(out, "\n      Digest:                ")?;
+            write_hex(out, h.digest)?;
+            writeln!(out, "\n      Flags:                 {}", h.flags.0)
+        }
+        Descriptor::Hashtree(h) => {
+            writeln!(out, "    Hashtree descriptor:")?;
+            writeln!(out, "      Version of dm-verity:  {}", h.dm_verity_version)?;
+            writeln!(out, "      Image Size:            {} bytes", h.image_size)?;
+            writeln!(out, "      Tree Offset:           {}", h.tree_offset)?;
+            writeln!(out, "      Tree Size:             {} bytes", h.tree_size)?;
+            writeln!(out, "      Data Block Size:       {} bytes", h.data_block_size)?;
+            writeln!(out, "      Hash Block Size:       {} bytes", h.hash_block_size)?;
+            writeln!(out, "      FEC num roots:         {}", h.fec_num_roots)?;
+            writeln!(out, "      FEC offset:            {}", h.fec_offset)?;
+            writeln!(out, "      FEC size:              {} bytes", h.fec_size)?;
+            writeln!(out, "      Hash Algorithm:        {}", h.hash_algorithm)?;
+            writeln!(out, "      Partition Name:        {}", h.partition_name)?;
+            write!(out, "      Salt:                  ")?;
+            write_hex(out, h.salt)?;
+            write!(out, "\n      Root Digest:           ")?;
+            write_hex(out, h.root_digest)?;
+            writeln!(out, "\n      Flags:                 {}", h.flags.0)
+        }
+        Descriptor::KernelCommandline(c) => {
+            writeln!(out, "    Kernel Cmdline descriptor:")?;
+            writeln!(out, "      Flags:                 {}", c.flags.0)?;
+            writeln!(out, "      Kernel Cmdline:        '{}'", c.commandline)
+        }
+        Descriptor::ChainPartition(c) => {
+            writeln!(out, "    Chain Partition descriptor:")?;
+            writeln!(out, "      Partition Name:          {}", c.partition_name)?;
+            writeln!(
+                out,
+                "      Rollback Index Location: {}",
+                c.rollback_index_location
+            )?;
+            writeln!(out, "      Flags:                   {}", c.flags.0)
+        }
+        Descriptor::Unknown(contents) => {
+            writeln!(out, "    Unknown descriptor: ({} bytes)", contents.len())
+        }
+        Descriptor::Custom(custom) => {
+            writeln!(
+                out,
+                "    Custom descriptor (tag {}): ({} bytes)",
+                custom.tag,
+                custom.body.len()
+            )
+        }
+    }
+}
+
+/// Returns true if all characters in `text` are printable (no control characters).
+fn is_printable(text: &str) -> bool {
+    !text.chars().any(|c| c.is_control())
+}
+
+/// Writes `bytes` to `out` as lowercase hex, matching avbtool's digest formatting.
+fn write_hex(out: &mut impl Write, bytes: &[u8]) -> fmt::Result {
+    for byte in bytes {
+        write!(out, "{byte:02x}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::{PropertyDescriptor, PropertyDescriptorHeader};
+    use core::ffi::CStr;
+
+    /// Builds a header matching the given key/value sizes for hand-crafted test descriptors.
+    fn test_header(key_num_bytes: u64, value_num_bytes: u64) -> PropertyDescriptorHeader {
+        PropertyDescriptorHeader {
+            tag: 0,
+            num_bytes_following: key_num_bytes + value_num_bytes + 2,
+            key_num_bytes,
+            value_num_bytes,
+        }
+    }
+
+    #[test]
+    fn dump_property_descriptor_matches_avbtool_format() {
+        let descriptor = Descriptor::Property(PropertyDescriptor {
+            key: "test.key",
+            key_cstr: CStr::from_bytes_with_nul(b"test.key\0").unwrap(),
+            value_with_nul: b"test value\0",
+            header: test_header(8, 10),
+        });
+
+        let mut rendered = String::new();
+        dump_descriptor(&descriptor, &mut rendered).unwrap();
+        assert_eq!(rendered, "    Prop: test.key -> 'test value'\n");
+    }
+
+    #[test]
+    fn dump_binary_property_descriptor_shows_byte_count() {
+        let descriptor = Descriptor::Property(PropertyDescriptor {
+            key: "test.key",
+            key_cstr: CStr::from_bytes_with_nul(b"test.key\0").unwrap(),
+            value_with_nul: &[0x01, 0x02, 0x03, 0x00],
+            header: test_header(8, 3),
+        });
+
+        let mut rendered = String::new();
+        dump_descriptor(&descriptor, &mut rendered).unwrap();
+        assert_eq!(rendered, "    Prop: test.key -> (3 bytes)\n");
+    }
+
+    #[test]
+    fn dump_redacted_hides_property_value() {
+        let descriptor = Descriptor::Property(PropertyDescriptor {
+            key: "test.key",
+            key_cstr: CStr::from_bytes_with_nul(b"test.key\0").unwrap(),
+            value_with_nul: b"device serial\0",
+            header: test_header(8, 13),
+        });
+
+        let mut full = String::new();
+        dump_descriptor(&descriptor, &mut full).unwrap();
+        assert_eq!(full, "    Prop: test.key -> 'device serial'\n");
+
+        let mut redacted = String::new();
+        dump_descriptor_redacted(&descriptor, &mut redacted).unwrap();
+        assert_eq!(redacted, "    Prop: test.key -> <redacted 13 bytes>\n");
+        assert!(!redacted.contains("device serial"));
+    }
+}
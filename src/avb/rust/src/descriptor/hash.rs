@@ -15,10 +15,13 @@
 //! Hash descriptors.
 
 use super::{
-    util::{parse_descriptor, split_slice, ValidateAndByteswap, ValidationFunc},
+    util::{
+        check_descriptor_tag, parse_descriptor, split_slice, validate_body_lengths,
+        ValidateAndByteswap, ValidationFunc,
+    },
     DescriptorResult,
 };
-use avb_bindgen::{avb_hash_descriptor_validate_and_byteswap, AvbHashDescriptor};
+use avb_bindgen::{avb_hash_descriptor_validate_and_byteswap, AvbDescriptorTag, AvbHashDescriptor};
 use core::{ffi::CStr, str::from_utf8};
 
 /// `AvbHashDescriptorFlags`; see libavb docs for details.
@@ -68,8 +71,17 @@ impl<'a> HashDescriptor<'a> {
     /// The new descriptor, or `DescriptorError` if the given `contents` aren't a valid
     /// `AvbHashDescriptor`.
     pub(super) fn new(contents: &'a [u8]) -> DescriptorResult<Self> {
+        check_descriptor_tag(contents, AvbDescriptorTag::AVB_DESCRIPTOR_TAG_HASH as u64)?;
         // Descriptor contains: header + name + salt + digest.
         let descriptor = parse_descriptor::<AvbHashDescriptor>(contents)?;
+        validate_body_lengths(
+            descriptor.body,
+            &[
+                descriptor.header.partition_name_len.into(),
+                descriptor.header.salt_len.into(),
+                descriptor.header.digest_len.into(),
+            ],
+        )?;
         let (partition_name, remainder) =
             split_slice(descriptor.body, descriptor.header.partition_name_len)?;
         let (salt, remainder) = split_slice(remainder, descriptor.header.salt_len)?;
@@ -15,10 +15,15 @@
 //! Hashtree descriptors.
 
 use super::{
-    util::{parse_descriptor, split_slice, ValidateAndByteswap, ValidationFunc},
+    util::{
+        check_descriptor_tag, parse_descriptor, split_slice, validate_body_lengths,
+        ValidateAndByteswap, ValidationFunc,
+    },
     DescriptorResult,
 };
-use avb_bindgen::{avb_hashtree_descriptor_validate_and_byteswap, AvbHashtreeDescriptor};
+use avb_bindgen::{
+    avb_hashtree_descriptor_validate_and_byteswap, AvbDescriptorTag, AvbHashtreeDescriptor,
+};
 use core::{ffi::CStr, str::from_utf8};
 
 /// `AvbHashtreeDescriptorFlags`; see libavb docs for details.
@@ -86,8 +91,17 @@ impl<'a> HashtreeDescriptor<'a> {
     /// The new descriptor, or `DescriptorError` if the given `contents` aren't a valid
     /// `AvbHashtreeDescriptor`.
     pub(super) fn new(contents: &'a [u8]) -> DescriptorResult<Self> {
+        check_descriptor_tag(contents, AvbDescriptorTag::AVB_DESCRIPTOR_TAG_HASHTREE as u64)?;
         // Descriptor contains: header + name + salt + digest.
         let descriptor = parse_descriptor::<AvbHashtreeDescriptor>(contents)?;
+        validate_body_lengths(
+            descriptor.body,
+            &[
+                descriptor.header.partition_name_len.into(),
+                descriptor.header.salt_len.into(),
+                descriptor.header.root_digest_len.into(),
+            ],
+        )?;
         let (partition_name, remainder) =
             split_slice(descriptor.body, descriptor.header.partition_name_len)?;
         let (salt, remainder) = split_slice(remainder, descriptor.header.salt_len)?;
@@ -148,6 +162,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn new_hashtree_descriptor_understated_digest_length_fails() {
+        let mut contents = test_contents();
+        // Zero out `root_digest_len` (bytes 112..116 of the packed header); libavb accepts
+        // lengths that merely fit in the body, so only the body length cross-check can catch
+        // the trailing digest bytes the header no longer accounts for.
+        contents[112..116].copy_from_slice(&0u32.to_be_bytes());
+        assert_eq!(
+            HashtreeDescriptor::new(&contents).unwrap_err(),
+            DescriptorError::InvalidSize
+        );
+    }
+
     #[test]
     fn new_hashtree_descriptor_too_short_contents_fails() {
         // The last 2 bytes are padding, so we need to drop 3 bytes to trigger an error.
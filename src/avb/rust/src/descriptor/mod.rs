@@ -21,9 +21,15 @@ extern crate alloc;
 
 mod chain;
 mod commandline;
+mod dump;
 mod hash;
 mod hashtree;
 mod property;
+#[cfg(any(test, feature = "std"))]
+mod reader;
+mod region;
+#[cfg(test)]
+mod testutil;
 mod util;
 
 use crate::VbmetaData;
@@ -40,9 +46,19 @@ use core::{
 
 pub use chain::{ChainPartitionDescriptor, ChainPartitionDescriptorFlags};
 pub use commandline::{KernelCommandlineDescriptor, KernelCommandlineDescriptorFlags};
+pub use dump::{dump_descriptor, dump_descriptor_redacted};
 pub use hash::{HashDescriptor, HashDescriptorFlags};
 pub use hashtree::{HashtreeDescriptor, HashtreeDescriptorFlags};
-pub use property::PropertyDescriptor;
+pub use property::{
+    ParseLimits, PropertyDescriptor, PropertyDescriptorHeader, is_conventional_key,
+};
+#[cfg(any(test, feature = "std"))]
+pub use reader::{DescriptorReader, OwnedDescriptor};
+pub use region::{
+    MergePolicy, OwnedProperty, RegionStats, canonicalize_region, encode_region,
+    find_descriptor_by_tag, first_invalid_offset, merge_regions, parse_and_digest,
+    parse_region_unique, region_encoded_len, region_stats, set_property_value_inplace,
+};
 
 /// A single descriptor.
 #[derive(Debug, PartialEq, Eq)]
@@ -59,8 +75,23 @@ pub enum Descriptor<'a> {
     ChainPartition(ChainPartitionDescriptor<'a>),
     /// Unknown descriptor type.
     Unknown(&'a [u8]),
+    /// Descriptor decoded by a caller-registered tag handler; see `parse_with()`.
+    Custom(CustomDescriptor<'a>),
+}
+
+/// A descriptor of an experimental type decoded by a caller-registered handler.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CustomDescriptor<'a> {
+    /// The descriptor tag.
+    pub tag: u64,
+    /// The decoded descriptor body, as selected by the handler.
+    pub body: &'a [u8],
 }
 
+/// Handler function decoding an experimental descriptor type from its raw contents
+/// (including the generic header).
+pub type TagHandler = for<'a> fn(&'a [u8]) -> DescriptorResult<CustomDescriptor<'a>>;
+
 /// Possible errors when extracting descriptors.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum DescriptorError {
@@ -72,31 +103,115 @@ pub enum DescriptorError {
     InvalidSize,
     /// A field that was supposed to be valid UTF-8 was not.
     InvalidUtf8,
+    /// A field that was supposed to be nul-terminated was not, or contained an interior nul.
+    InvalidNulTermination,
     /// Descriptor contents don't match what we expect.
     InvalidContents,
+    /// Two property descriptors in a region share a key; carries the offending key. Only
+    /// returned by the strict `parse_region_unique()` — the default parsers stay
+    /// permissive about duplicates.
+    DuplicateKey(alloc::string::String),
+    /// The descriptor tag doesn't match the type being parsed.
+    WrongType {
+        /// The tag required by the parser.
+        expected: u64,
+        /// The tag found in the descriptor header.
+        found: u64,
+    },
 }
 
+impl DescriptorError {
+    /// Converts the error into a stable numeric code for FFI and cross-language tooling.
+    ///
+    /// The mapping is part of the API: codes are never reused or renumbered, and new
+    /// variants get the next free code. Variant payloads (the duplicated key, the
+    /// expected/found tags) are not encoded; callers needing them must stay in Rust.
+    ///
+    /// | Code | Variant                 |
+    /// |------|-------------------------|
+    /// | 1    | `InvalidHeader`         |
+    /// | 2    | `InvalidValue`          |
+    /// | 3    | `InvalidSize`           |
+    /// | 4    | `InvalidUtf8`           |
+    /// | 5    | `InvalidNulTermination` |
+    /// | 6    | `InvalidContents`       |
+    /// | 7    | `DuplicateKey`          |
+    /// | 8    | `WrongType`             |
+    pub fn code(&self) -> u32 {
+        // No catch-all: adding a variant without assigning it a code must not compile.
+        match self {
+            Self::InvalidHeader => 1,
+            Self::InvalidValue => 2,
+            Self::InvalidSize => 3,
+            Self::InvalidUtf8 => 4,
+            Self::InvalidNulTermination => 5,
+            Self::InvalidContents => 6,
+            Self::DuplicateKey(_) => 7,
+            Self::WrongType { .. } => 8,
+        }
+    }
+
+    /// Reconstructs the error variant for a code produced by `code()`.
+    ///
+    /// Since codes don't carry variant payloads, the returned `DuplicateKey` and
+    /// `WrongType` variants hold empty placeholders. Returns `None` for unknown codes.
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            1 => Some(Self::InvalidHeader),
+            2 => Some(Self::InvalidValue),
+            3 => Some(Self::InvalidSize),
+            4 => Some(Self::InvalidUtf8),
+            5 => Some(Self::InvalidNulTermination),
+            6 => Some(Self::InvalidContents),
+            7 => Some(Self::DuplicateKey(alloc::string::String::new())),
+            8 => Some(Self::WrongType { expected: 0, found: 0 }),
+            _ => None,
+        }
+    }
+}
+
+/// A descriptor string field (e.g. a property key or partition name) was not valid UTF-8.
 impl From<Utf8Error> for DescriptorError {
     fn from(_: Utf8Error) -> Self {
         Self::InvalidUtf8
     }
 }
 
+/// A descriptor field that libavb guarantees to be nul-terminated was missing its terminator
+/// or contained an interior nul byte.
 impl From<FromBytesWithNulError> for DescriptorError {
     fn from(_: FromBytesWithNulError) -> Self {
-        Self::InvalidContents
+        Self::InvalidNulTermination
     }
 }
 
+/// A fixed-size descriptor field expected to hold a nul-terminated string (e.g. a hash
+/// algorithm name) had no nul byte.
 impl From<FromBytesUntilNulError> for DescriptorError {
     fn from(_: FromBytesUntilNulError) -> Self {
-        Self::InvalidContents
+        Self::InvalidNulTermination
     }
 }
 
 /// `Result` type for `DescriptorError` errors.
 pub type DescriptorResult<T> = Result<T, DescriptorError>;
 
+/// Non-fatal anomalies noticed while parsing a descriptor.
+///
+/// These describe descriptors that are technically valid but suspicious enough to flag
+/// when linting images; see `PropertyDescriptor::new_checked()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DescriptorWarning {
+    /// The property key is empty.
+    EmptyKey,
+    /// The property value is nonempty but contains only zero bytes, which usually means
+    /// padding was mistaken for a value when the image was built.
+    AllPaddingValue,
+    /// The property key contains whitespace, control characters, or non-ASCII bytes; see
+    /// `is_conventional_key()`.
+    UnconventionalKey,
+}
+
 impl Descriptor<'_> {
     /// Extracts the fully-typed descriptor from the generic `AvbDescriptor` header.
     ///
@@ -153,6 +268,257 @@ impl Descriptor<'_> {
             _ => Ok(Descriptor::Unknown(contents)),
         }
     }
+
+    /// Parses a single descriptor from raw contents, consulting `handlers` for tags this
+    /// crate doesn't know.
+    ///
+    /// Known tags dispatch to the typed parsers exactly like descriptor extraction from a
+    /// vbmeta image. An unknown tag is first looked up in `handlers`; without a registered
+    /// handler the default `Unknown` path applies, so forward compatibility is preserved.
+    ///
+    /// # Arguments
+    /// * `contents`: raw descriptor bytes, starting at the generic header.
+    /// * `handlers`: `(tag, handler)` pairs for experimental descriptor types.
+    ///
+    /// # Returns
+    /// The parsed descriptor, or `DescriptorError` if the header or a matching parser
+    /// (including a registered handler) rejected the contents.
+    pub fn parse_with<'a>(
+        contents: &'a [u8],
+        handlers: &[(u64, TagHandler)],
+    ) -> DescriptorResult<Descriptor<'a>> {
+        let (tag, total_size) = region::peek_descriptor_header(contents)?;
+        let (contents, _) = util::split_slice(contents, total_size)?;
+
+        match tag.try_into() {
+            Ok(AvbDescriptorTag::AVB_DESCRIPTOR_TAG_PROPERTY) => {
+                Ok(Descriptor::Property(PropertyDescriptor::new(contents)?))
+            }
+            Ok(AvbDescriptorTag::AVB_DESCRIPTOR_TAG_HASHTREE) => {
+                Ok(Descriptor::Hashtree(HashtreeDescriptor::new(contents)?))
+            }
+            Ok(AvbDescriptorTag::AVB_DESCRIPTOR_TAG_HASH) => {
+                Ok(Descriptor::Hash(HashDescriptor::new(contents)?))
+            }
+            Ok(AvbDescriptorTag::AVB_DESCRIPTOR_TAG_KERNEL_CMDLINE) => Ok(
+                Descriptor::KernelCommandline(KernelCommandlineDescriptor::new(contents)?),
+            ),
+            Ok(AvbDescriptorTag::AVB_DESCRIPTOR_TAG_CHAIN_PARTITION) => Ok(
+                Descriptor::ChainPartition(ChainPartitionDescriptor::new(contents)?),
+            ),
+            _ => match handlers.iter().find(|(handler_tag, _)| *handler_tag == tag) {
+                Some((_, handler)) => Ok(Descriptor::Custom(handler(contents)?)),
+                None => Ok(Descriptor::Unknown(contents)),
+            },
+        }
+    }
+}
+
+/// Iterator over the descriptors in a raw descriptor region.
+///
+/// Unlike `get_descriptors()`, which decodes everything eagerly via libavb, this walks the
+/// region lazily so callers can stop early. A malformed header terminates iteration after
+/// yielding the error.
+pub struct DescriptorIterator<'a> {
+    /// The unvisited tail of the region.
+    remaining: &'a [u8],
+}
+
+impl<'a> DescriptorIterator<'a> {
+    /// Creates an iterator over the given raw descriptor region bytes.
+    pub fn new(region: &'a [u8]) -> Self {
+        Self { remaining: region }
+    }
+
+    /// Adapts the iterator to yield only property descriptors, skipping other types.
+    ///
+    /// Parse errors are still surfaced, so a malformed region cannot be mistaken for one
+    /// that simply has no properties.
+    pub fn properties(self) -> impl Iterator<Item = DescriptorResult<PropertyDescriptor<'a>>> {
+        self.filter_map(|result| match result {
+            Ok(Descriptor::Property(descriptor)) => Some(Ok(descriptor)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+
+    /// Adapts the iterator to yield only hash descriptors, skipping other types.
+    pub fn hashes(self) -> impl Iterator<Item = DescriptorResult<HashDescriptor<'a>>> {
+        self.filter_map(|result| match result {
+            Ok(Descriptor::Hash(descriptor)) => Some(Ok(descriptor)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+}
+
+impl<'a> Iterator for DescriptorIterator<'a> {
+    type Item = DescriptorResult<Descriptor<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining.is_empty() {
+            return None;
+        }
+        let split = region::peek_descriptor_header(self.remaining)
+            .and_then(|(_, total_size)| util::split_slice(self.remaining, total_size));
+        match split {
+            Ok((contents, rest)) => {
+                self.remaining = rest;
+                Some(Descriptor::parse_with(contents, &[]))
+            }
+            Err(e) => {
+                // Without a trustworthy size we can't find the next descriptor, so stop.
+                self.remaining = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Conventional property keys under which avbtool stores the build fingerprint, in lookup
+/// order.
+const FINGERPRINT_KEYS: &[&str] = &[
+    "com.android.build.system.fingerprint",
+    "com.android.build.vendor.fingerprint",
+    "com.android.build.boot.fingerprint",
+];
+
+/// Conventional property keys under which avbtool stores the security patch level, in
+/// lookup order.
+const SECURITY_PATCH_KEYS: &[&str] = &[
+    "com.android.build.system.security_patch",
+    "com.android.build.vendor.security_patch",
+    "com.android.build.boot.security_patch",
+];
+
+/// Returns the value of the first property with the given key, without its nul terminator.
+fn property_value<'a>(descriptors: &'a [Descriptor], key: &str) -> Option<&'a [u8]> {
+    descriptors.iter().find_map(|descriptor| match descriptor {
+        Descriptor::Property(p) if p.has_key(key) => {
+            Some(&p.value_with_nul[..p.value_with_nul.len() - 1])
+        }
+        _ => None,
+    })
+}
+
+/// Looks up the first conventional key from `keys` holding a UTF-8 value.
+fn well_known_property(descriptors: &[Descriptor], keys: &[&str]) -> Option<alloc::string::String> {
+    keys.iter().find_map(|key| {
+        let value = property_value(descriptors, key)?;
+        Some(core::str::from_utf8(value).ok()?.into())
+    })
+}
+
+/// Returns the build fingerprint stored in the given descriptors, if any.
+///
+/// AVB images conventionally store the fingerprint under a per-partition
+/// `com.android.build.<partition>.fingerprint` property; this checks the usual partitions
+/// so callers don't hardcode the key strings.
+pub fn fingerprint(descriptors: &[Descriptor]) -> Option<alloc::string::String> {
+    well_known_property(descriptors, FINGERPRINT_KEYS)
+}
+
+/// Returns the security patch level stored in the given descriptors, if any.
+///
+/// The conventional keys mirror the fingerprint scheme; see `fingerprint()`.
+pub fn security_patch_level(descriptors: &[Descriptor]) -> Option<alloc::string::String> {
+    well_known_property(descriptors, SECURITY_PATCH_KEYS)
+}
+
+/// Assembles the effective kernel command line for a given hashtree mode.
+///
+/// Mirrors the bootloader's selection: a fragment flagged
+/// `USE_ONLY_IF_HASHTREE_NOT_DISABLED` only applies while hashtree verification is
+/// enabled, one flagged `USE_ONLY_IF_HASHTREE_DISABLED` only while it is disabled, and an
+/// unflagged fragment always applies. Applicable fragments are joined with single spaces
+/// in descriptor order.
+///
+/// # Arguments
+/// * `descriptors`: descriptors to collect command line fragments from.
+/// * `hashtree_enabled`: whether hashtree verification is enabled.
+///
+/// # Returns
+/// The assembled command line, empty if no fragment applies.
+pub fn assemble_cmdline(
+    descriptors: &[Descriptor],
+    hashtree_enabled: bool,
+) -> alloc::string::String {
+    let mut cmdline = alloc::string::String::new();
+    for descriptor in descriptors {
+        let Descriptor::KernelCommandline(fragment) = descriptor else {
+            continue;
+        };
+        let flags = fragment.flags.0;
+        let applicable = if flags
+            & KernelCommandlineDescriptorFlags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_NOT_DISABLED.0
+            != 0
+        {
+            hashtree_enabled
+        } else if flags
+            & KernelCommandlineDescriptorFlags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_DISABLED.0
+            != 0
+        {
+            !hashtree_enabled
+        } else {
+            true
+        };
+        if applicable && !fragment.commandline.is_empty() {
+            if !cmdline.is_empty() {
+                cmdline.push(' ');
+            }
+            cmdline.push_str(fragment.commandline);
+        }
+    }
+    cmdline
+}
+
+/// Parses every descriptor in a region, continuing past corrupt entries.
+///
+/// Unlike `DescriptorIterator`, which stops at the first malformed header, this records the
+/// error and attempts to resync: it scans forward in 8-byte steps (the descriptor alignment
+/// unit) for the next position that looks like a valid header whose claimed size fits in
+/// the remaining data. If no such position exists the scan stops. This gives best-effort
+/// recovery when examining possibly-corrupt images: all the good descriptors are returned
+/// alongside per-entry errors rather than an early abort.
+///
+/// # Arguments
+/// * `region`: raw descriptor region bytes.
+///
+/// # Returns
+/// One entry per descriptor (or corrupt span), in region order.
+pub fn parse_all(region: &[u8]) -> Vec<DescriptorResult<Descriptor>> {
+    let mut results = Vec::new();
+    let mut remaining = region;
+    while !remaining.is_empty() {
+        let split = region::peek_descriptor_header(remaining)
+            .and_then(|(_, total_size)| util::split_slice(remaining, total_size));
+        match split {
+            Ok((contents, rest)) => {
+                // A body parse failure still has a trustworthy size, so no resync needed.
+                results.push(Descriptor::parse_with(contents, &[]));
+                remaining = rest;
+            }
+            Err(e) => {
+                results.push(Err(e));
+                let resync = (8..remaining.len()).step_by(8).find(|&offset| {
+                    // Require a non-empty body: every real descriptor has one, and an
+                    // all-zero `num_bytes_following` would make almost any bytes look
+                    // like a valid header.
+                    region::peek_descriptor_header(&remaining[offset..]).is_ok_and(
+                        |(_, total_size)| {
+                            total_size > region::GENERIC_HEADER_SIZE
+                                && total_size <= remaining.len() - offset
+                        },
+                    )
+                });
+                match resync {
+                    Some(offset) => remaining = &remaining[offset..],
+                    None => break,
+                }
+            }
+        }
+    }
+    results
 }
 
 /// Returns a vector of descriptors extracted from the given vbmeta image.
@@ -236,6 +602,7 @@ unsafe extern "C" fn fill_descriptors_vec(
 
 #[cfg(test)]
 mod tests {
+    use super::testutil::fake_property_descriptor;
     use super::*;
 
     #[test]
@@ -257,6 +624,194 @@ mod tests {
         assert_eq!(data, contents);
     }
 
+    // A fake descriptor which is valid but with an unknown tag.
+    const UNKNOWN_TAG_DESCRIPTOR: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x42, // tag = 0x42u64 (BE)
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, // num_bytes_following = 8u64 (BE)
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, // fake contents
+    ];
+
+    /// Test handler that strips the generic header off the descriptor contents.
+    fn body_handler(contents: &[u8]) -> DescriptorResult<CustomDescriptor> {
+        Ok(CustomDescriptor {
+            tag: 0x42,
+            body: &contents[16..],
+        })
+    }
+
+    #[test]
+    fn parse_with_registered_handler_returns_custom() {
+        let descriptor =
+            Descriptor::parse_with(UNKNOWN_TAG_DESCRIPTOR, &[(0x42, body_handler as TagHandler)])
+                .unwrap();
+        assert_eq!(
+            descriptor,
+            Descriptor::Custom(CustomDescriptor {
+                tag: 0x42,
+                body: &[1, 2, 3, 4, 5, 6, 7, 8],
+            })
+        );
+    }
+
+    #[test]
+    fn parse_with_unregistered_tag_returns_unknown() {
+        let descriptor = Descriptor::parse_with(UNKNOWN_TAG_DESCRIPTOR, &[]).unwrap();
+        assert_eq!(descriptor, Descriptor::Unknown(UNKNOWN_TAG_DESCRIPTOR));
+    }
+
+    #[test]
+    fn iterator_walks_mixed_region() {
+        let mut region = fake_property_descriptor(b"key", b"value");
+        region.extend_from_slice(UNKNOWN_TAG_DESCRIPTOR);
+
+        let descriptors: Vec<_> = DescriptorIterator::new(&region).collect();
+        assert_eq!(descriptors.len(), 2);
+        assert!(matches!(descriptors[0], Ok(Descriptor::Property(_))));
+        assert!(matches!(descriptors[1], Ok(Descriptor::Unknown(_))));
+    }
+
+    #[test]
+    fn iterator_properties_filters_other_types() {
+        let mut region = Vec::from(UNKNOWN_TAG_DESCRIPTOR);
+        region.extend_from_slice(&fake_property_descriptor(b"key.one", b"1"));
+        region.extend_from_slice(&fake_property_descriptor(b"key.two", b"2"));
+
+        let properties: Vec<_> = DescriptorIterator::new(&region)
+            .properties()
+            .collect::<DescriptorResult<_>>()
+            .unwrap();
+        assert_eq!(properties.len(), 2);
+        assert_eq!(properties[0].key, "key.one");
+        assert_eq!(properties[1].key, "key.two");
+    }
+
+    #[test]
+    fn fingerprint_finds_conventional_key() {
+        let mut region = fake_property_descriptor(b"other.key", b"other");
+        region.extend_from_slice(&fake_property_descriptor(
+            b"com.android.build.system.fingerprint",
+            b"brand/product/device:14/BUILD/1:user/release-keys",
+        ));
+
+        let descriptors: Vec<_> = parse_all(&region).into_iter().collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            fingerprint(&descriptors).as_deref(),
+            Some("brand/product/device:14/BUILD/1:user/release-keys")
+        );
+        assert_eq!(security_patch_level(&descriptors), None);
+    }
+
+    #[test]
+    fn security_patch_level_finds_conventional_key() {
+        let region =
+            fake_property_descriptor(b"com.android.build.vendor.security_patch", b"2026-08-05");
+
+        let descriptors: Vec<_> = parse_all(&region).into_iter().collect::<Result<_, _>>().unwrap();
+        assert_eq!(security_patch_level(&descriptors).as_deref(), Some("2026-08-05"));
+        assert_eq!(fingerprint(&descriptors), None);
+    }
+
+    #[test]
+    fn parse_all_recovers_around_corrupt_descriptor() {
+        let mut region = fake_property_descriptor(b"before", b"1");
+        // A corrupt 24-byte span: `num_bytes_following` is not 8-byte aligned, and the
+        // contents can't be mistaken for a descriptor header during resync.
+        region.extend_from_slice(&0x42u64.to_be_bytes());
+        region.extend_from_slice(&7u64.to_be_bytes());
+        region.extend_from_slice(&[0xff; 8]);
+        region.extend_from_slice(&fake_property_descriptor(b"after", b"2"));
+
+        let results = parse_all(&region);
+        assert_eq!(results.len(), 3);
+        assert!(matches!(&results[0], Ok(Descriptor::Property(p)) if p.key == "before"));
+        assert_eq!(results[1], Err(DescriptorError::InvalidHeader));
+        assert!(matches!(&results[2], Ok(Descriptor::Property(p)) if p.key == "after"));
+    }
+
+    #[test]
+    fn parse_all_unrecoverable_corruption_stops_after_error() {
+        // Nothing after the corrupt header resembles a valid descriptor.
+        let mut region = Vec::new();
+        region.extend_from_slice(&0x42u64.to_be_bytes());
+        region.extend_from_slice(&7u64.to_be_bytes());
+        region.extend_from_slice(&[0xff; 16]);
+
+        let results = parse_all(&region);
+        assert_eq!(results, vec![Err(DescriptorError::InvalidHeader)]);
+    }
+
+    #[test]
+    fn iterator_malformed_header_yields_error_then_stops() {
+        // `num_bytes_following` is not 8-byte aligned.
+        let region: &[u8] = &[
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x42, // tag = 0x42u64 (BE)
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, // num_bytes_following = 7u64 (BE)
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, // fake contents
+        ];
+        let mut iterator = DescriptorIterator::new(region);
+        assert_eq!(iterator.next(), Some(Err(DescriptorError::InvalidHeader)));
+        assert_eq!(iterator.next(), None);
+    }
+
+    /// Builds a command line fragment descriptor with the given raw flags.
+    fn cmdline_fragment(flags: u32, commandline: &str) -> Descriptor {
+        Descriptor::KernelCommandline(KernelCommandlineDescriptor {
+            flags: KernelCommandlineDescriptorFlags(flags as _),
+            commandline,
+        })
+    }
+
+    #[test]
+    fn assemble_cmdline_selects_fragments_by_hashtree_mode() {
+        let descriptors = [
+            cmdline_fragment(0, "console=ttyS0"),
+            cmdline_fragment(
+                KernelCommandlineDescriptorFlags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_NOT_DISABLED.0
+                    as u32,
+                "dm=verity",
+            ),
+            cmdline_fragment(
+                KernelCommandlineDescriptorFlags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_DISABLED.0
+                    as u32,
+                "root=/dev/vda",
+            ),
+        ];
+        assert_eq!(assemble_cmdline(&descriptors, true), "console=ttyS0 dm=verity");
+        assert_eq!(assemble_cmdline(&descriptors, false), "console=ttyS0 root=/dev/vda");
+    }
+
+    #[test]
+    fn assemble_cmdline_no_applicable_fragments_is_empty() {
+        assert_eq!(assemble_cmdline(&[], true), "");
+        let descriptors = [cmdline_fragment(
+            KernelCommandlineDescriptorFlags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_DISABLED.0
+                as u32,
+            "root=/dev/vda",
+        )];
+        assert_eq!(assemble_cmdline(&descriptors, true), "");
+    }
+
+    #[test]
+    fn error_codes_round_trip_for_all_variants() {
+        let variants = [
+            DescriptorError::InvalidHeader,
+            DescriptorError::InvalidValue,
+            DescriptorError::InvalidSize,
+            DescriptorError::InvalidUtf8,
+            DescriptorError::InvalidNulTermination,
+            DescriptorError::InvalidContents,
+            DescriptorError::DuplicateKey(alloc::string::String::new()),
+            DescriptorError::WrongType { expected: 0, found: 0 },
+        ];
+        for (index, error) in variants.iter().enumerate() {
+            // Codes are assigned sequentially starting at 1 and must never be renumbered.
+            assert_eq!(error.code(), index as u32 + 1);
+            assert_eq!(DescriptorError::from_code(error.code()), Some(error.clone()));
+        }
+        assert_eq!(DescriptorError::from_code(0), None);
+        assert_eq!(DescriptorError::from_code(variants.len() as u32 + 1), None);
+    }
+
     #[test]
     fn new_invalid_descriptor_length_fails() {
         // `avb_descriptor_validate_and_byteswap()` should detect and reject descriptors whose
@@ -15,11 +15,71 @@
 //! Property descriptors.
 
 use super::{
-    DescriptorError, DescriptorResult,
-    util::{ValidateAndByteswap, ValidationFunc, parse_descriptor, split_slice},
+    DescriptorError, DescriptorResult, DescriptorWarning,
+    util::{
+        ValidateAndByteswap, ValidationFunc, check_descriptor_tag, parse_descriptor, split_slice,
+        validate_body_lengths,
+    },
 };
-use avb_bindgen::{AvbPropertyDescriptor, avb_property_descriptor_validate_and_byteswap};
-use core::ffi::CStr;
+use alloc::vec::Vec;
+use avb_bindgen::{
+    AvbDescriptor, AvbDescriptorTag, AvbPropertyDescriptor, AvbSHA256Ctx, avb_sha256_final,
+    avb_sha256_init, avb_sha256_update, avb_property_descriptor_validate_and_byteswap,
+};
+use core::{ffi::CStr, mem::size_of};
+
+/// Size in bytes of the `AvbPropertyDescriptor` header as laid out by bindgen.
+///
+/// Centralized here so that parsing bounds and tests share a single source of truth; if the
+/// bindgen layout ever shifts it will surface via the compile-time assertion below rather than
+/// as a runtime parse bug.
+pub(super) const HEADER_SIZE: usize = size_of::<AvbPropertyDescriptor>();
+
+// Descriptors are always a multiple of 8 bytes; a zero-sized or misaligned header would mean
+// the bindgen layout is broken.
+const _: () = assert!(HEADER_SIZE > 0 && HEADER_SIZE % 8 == 0);
+
+/// Size limits applied by `PropertyDescriptor::new_with_limits()`.
+///
+/// A malicious header can claim gigabyte-sized keys or values; tools ingesting arbitrary
+/// images can use these limits to bound memory before the body is touched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Maximum key length in bytes, excluding the nul terminator.
+    pub max_key: usize,
+    /// Maximum value length in bytes, excluding the nul terminator.
+    pub max_value: usize,
+    /// Maximum total encoded descriptor size in bytes, including the header.
+    pub max_total: usize,
+}
+
+/// Generous defaults that accept anything a real avbtool image produces while still
+/// rejecting obviously hostile headers.
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_key: 1024,
+            max_value: 64 * 1024,
+            max_total: 128 * 1024,
+        }
+    }
+}
+
+/// Safe owned copy of the numeric `AvbPropertyDescriptor` header fields.
+///
+/// All fields are byte-swapped to host order, so callers never need to touch the
+/// `avb_bindgen` types directly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PropertyDescriptorHeader {
+    /// Generic descriptor tag; always `AVB_DESCRIPTOR_TAG_PROPERTY`.
+    pub tag: u64,
+    /// Number of bytes following the generic descriptor header.
+    pub num_bytes_following: u64,
+    /// Length of the key in bytes, excluding the nul terminator.
+    pub key_num_bytes: u64,
+    /// Length of the value in bytes, excluding the nul terminator.
+    pub value_num_bytes: u64,
+}
 
 /// Wraps an `AvbPropertyDescriptor` stored in a vbmeta image.
 #[derive(Debug, PartialEq, Eq)]
@@ -31,6 +91,9 @@ pub struct PropertyDescriptor<'a> {
 
     /// Value can be arbitrary bytes.
     pub value_with_nul: &'a [u8],
+
+    /// Host-order copy of the header, exposed via `header()`.
+    pub(crate) header: PropertyDescriptorHeader,
 }
 
 // SAFETY: `VALIDATE_AND_BYTESWAP_FUNC` is the correct libavb validator for this descriptor type.
@@ -49,8 +112,46 @@ impl<'a> PropertyDescriptor<'a> {
     /// The new descriptor, or `DescriptorError` if the given `contents` aren't a valid
     /// `AvbPropertyDescriptor`.
     pub(super) fn new(contents: &'a [u8]) -> DescriptorResult<Self> {
+        Self::new_with_limits(contents, &ParseLimits::default())
+    }
+
+    /// Extract a `PropertyDescriptor`, rejecting descriptors that exceed the given limits.
+    ///
+    /// Limits are checked against the header fields alone, before any of the descriptor body
+    /// is parsed, so an oversized claim never drives memory usage.
+    ///
+    /// # Arguments
+    /// * `contents`: descriptor contents, including the header, in raw big-endian format.
+    /// * `limits`: maximum key, value, and total sizes to accept.
+    ///
+    /// # Returns
+    /// The new descriptor, `DescriptorError::InvalidSize` if any limit is exceeded, or another
+    /// `DescriptorError` if the given `contents` aren't a valid `AvbPropertyDescriptor`.
+    pub fn new_with_limits(contents: &'a [u8], limits: &ParseLimits) -> DescriptorResult<Self> {
+        // Check the header bound against the shared constant up front; `parse_descriptor()`
+        // performs the same check internally but this keeps the layout assumption in one place.
+        if contents.len() < HEADER_SIZE {
+            return Err(DescriptorError::InvalidHeader);
+        }
+        check_descriptor_tag(contents, AvbDescriptorTag::AVB_DESCRIPTOR_TAG_PROPERTY as u64)?;
+
         // Descriptor contains: header + key + nul + value + nul.
         let descriptor = parse_descriptor::<AvbPropertyDescriptor>(contents)?;
+        let total_size = (size_of::<AvbDescriptor>() as u64)
+            .checked_add(descriptor.header.parent_descriptor.num_bytes_following)
+            .ok_or(DescriptorError::InvalidValue)?;
+        if total_size > limits.max_total as u64
+            || descriptor.header.key_num_bytes > limits.max_key as u64
+            || descriptor.header.value_num_bytes > limits.max_value as u64
+        {
+            return Err(DescriptorError::InvalidSize);
+        }
+        // Body layout: key + nul + value + nul (+ alignment padding).
+        validate_body_lengths(
+            descriptor.body,
+            &[descriptor.header.key_num_bytes, 1, descriptor.header.value_num_bytes, 1],
+        )?;
+
         // Guaranteed to be nul terminated by libavb.
         let (key_bytes_with_nul, remainder) =
             split_slice(descriptor.body, descriptor.header.key_num_bytes + 1)?;
@@ -69,15 +170,179 @@ impl<'a> PropertyDescriptor<'a> {
             key,
             key_cstr,
             value_with_nul,
+            header: PropertyDescriptorHeader {
+                tag: descriptor.header.parent_descriptor.tag,
+                num_bytes_following: descriptor.header.parent_descriptor.num_bytes_following,
+                key_num_bytes: descriptor.header.key_num_bytes,
+                value_num_bytes: descriptor.header.value_num_bytes,
+            },
         })
     }
+
+    /// Returns an owned copy of the descriptor's numeric header fields in host byte order.
+    pub fn header(&self) -> PropertyDescriptorHeader {
+        self.header
+    }
+
+    /// Extracts a `PropertyDescriptor` and reports how many bytes it consumed.
+    ///
+    /// Useful when composing with other binary parsers: the returned length is the full
+    /// 8-byte-aligned encoded descriptor size, so a manual cursor can be advanced past this
+    /// descriptor in a single step.
+    ///
+    /// # Arguments
+    /// * `contents`: descriptor contents, including the header, in raw big-endian format.
+    ///
+    /// # Returns
+    /// The new descriptor and the number of bytes it occupies in `contents`, or
+    /// `DescriptorError` if parsing failed.
+    pub fn new_counting(contents: &'a [u8]) -> DescriptorResult<(Self, usize)> {
+        let descriptor = Self::new(contents)?;
+        // `num_bytes_following` is validated to be 8-byte aligned, so the sum keeps the
+        // required alignment.
+        let consumed = descriptor
+            .header
+            .num_bytes_following
+            .checked_add(size_of::<AvbDescriptor>() as u64)
+            .ok_or(DescriptorError::InvalidValue)?
+            .try_into()
+            .map_err(|_| DescriptorError::InvalidValue)?;
+        Ok((descriptor, consumed))
+    }
+
+    /// Returns the byte offset of the key within the encoded descriptor.
+    ///
+    /// Offsets are relative to the start of the descriptor contents (i.e. the header), so
+    /// in-place patching tools can index directly into the buffer the descriptor was parsed
+    /// from. The key starts immediately after the fixed-size header.
+    pub fn key_offset(&self) -> usize {
+        HEADER_SIZE
+    }
+
+    /// Returns the byte offset of the value within the encoded descriptor.
+    ///
+    /// The value follows the key and its nul terminator. Like `key_offset()`, the offset is
+    /// relative to the start of the descriptor contents.
+    pub fn value_offset(&self) -> usize {
+        HEADER_SIZE + self.header.key_num_bytes as usize + 1
+    }
+
+    /// Extracts a `PropertyDescriptor` and collects non-fatal anomalies.
+    ///
+    /// Parsing is exactly as strict as `new()`; the warnings flag descriptors that parse
+    /// fine but look wrong (empty key, a value that is nothing but zero padding), which a
+    /// linting tool wants to surface without rejecting the image.
+    ///
+    /// # Arguments
+    /// * `contents`: descriptor contents, including the header, in raw big-endian format.
+    ///
+    /// # Returns
+    /// The new descriptor along with any warnings, or `DescriptorError` if the given
+    /// `contents` aren't a valid `AvbPropertyDescriptor`.
+    pub fn new_checked(contents: &'a [u8]) -> DescriptorResult<(Self, Vec<DescriptorWarning>)> {
+        let descriptor = Self::new(contents)?;
+        let mut warnings = Vec::new();
+        if descriptor.key.is_empty() {
+            warnings.push(DescriptorWarning::EmptyKey);
+        } else if !is_conventional_key(descriptor.key) {
+            warnings.push(DescriptorWarning::UnconventionalKey);
+        }
+        let value = &descriptor.value_with_nul[..descriptor.value_with_nul.len() - 1];
+        if !value.is_empty() && value.iter().all(|&byte| byte == 0) {
+            warnings.push(DescriptorWarning::AllPaddingValue);
+        }
+        Ok((descriptor, warnings))
+    }
+
+    /// Computes a SHA-256 digest over the descriptor's canonical form.
+    ///
+    /// The digest covers `key_num_bytes || key || value_num_bytes || value` with the
+    /// lengths as big-endian `u64` and without nul terminators or alignment padding, so two
+    /// descriptors that differ only in byte layout (e.g. trailing padding) produce the same
+    /// digest. This makes it suitable as a cache or dedup key for the semantic content.
+    pub fn content_digest(&self) -> [u8; 32] {
+        let value = &self.value_with_nul[..self.value_with_nul.len() - 1];
+        let mut canonical = Vec::with_capacity(8 + self.key.len() + 8 + value.len());
+        canonical.extend_from_slice(&self.header.key_num_bytes.to_be_bytes());
+        canonical.extend_from_slice(self.key.as_bytes());
+        canonical.extend_from_slice(&self.header.value_num_bytes.to_be_bytes());
+        canonical.extend_from_slice(value);
+
+        let mut digest = [0u8; 32];
+        let mut ctx = core::mem::MaybeUninit::<AvbSHA256Ctx>::uninit();
+        // SAFETY:
+        // * `avb_sha256_init()` fully initializes the context before the other calls use it.
+        // * `canonical` outlives the update call and its length is passed alongside it.
+        // * `avb_sha256_final()` returns a pointer to the 32-byte digest inside `ctx`,
+        //   which remains valid until `ctx` is dropped after the copy.
+        unsafe {
+            avb_sha256_init(ctx.as_mut_ptr());
+            avb_sha256_update(ctx.as_mut_ptr(), canonical.as_ptr(), canonical.len());
+            let digest_ptr = avb_sha256_final(ctx.as_mut_ptr());
+            digest.copy_from_slice(core::slice::from_raw_parts(digest_ptr, digest.len()));
+        }
+        digest
+    }
+
+    /// Returns true if the descriptor's key exactly matches `target`.
+    ///
+    /// Compares against `key` rather than `key_cstr`, so no allocation or nul handling is
+    /// involved; this is the intended comparison for hot scanning loops.
+    pub fn has_key(&self, target: &str) -> bool {
+        self.key == target
+    }
+
+    /// Compares two descriptors by semantic content: key and value, excluding the trailing
+    /// nul and alignment padding.
+    ///
+    /// Derived `==` also compares the header, so two encodings of the same property that
+    /// differ only in how much padding follows the value compare unequal there. Diff
+    /// tooling that shouldn't flag padding-only changes wants this comparison instead.
+    pub fn semantic_eq(&self, other: &PropertyDescriptor) -> bool {
+        self.key == other.key
+            && self.value_with_nul[..self.value_with_nul.len() - 1]
+                == other.value_with_nul[..other.value_with_nul.len() - 1]
+    }
+
+    /// Returns true if the value (excluding the trailing nul) is human-readable text.
+    ///
+    /// A value qualifies as text when it is valid UTF-8 containing no control characters
+    /// other than common whitespace (tab, newline, carriage return). This drives whether a
+    /// caller shows the string directly or falls back to a hex dump. An empty value counts
+    /// as text. The check is a single pass over the value.
+    pub fn value_is_text(&self) -> bool {
+        let value = &self.value_with_nul[..self.value_with_nul.len() - 1];
+        match core::str::from_utf8(value) {
+            Ok(text) => text
+                .chars()
+                .all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r')),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Checks whether a property key follows AVB naming conventions.
+///
+/// Conventional keys use a reverse-DNS-style dotted namespace like
+/// `com.android.build.system.fingerprint`: non-empty, printable ASCII, and free of
+/// whitespace and control characters. Unconventional keys still parse — this is a linting
+/// aid for catching typos and malformed names, not a validity check.
+///
+/// # Arguments
+/// * `key`: the property key to check.
+///
+/// # Returns
+/// True if the key is non-empty printable ASCII without whitespace, false otherwise.
+pub fn is_conventional_key(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|byte| byte.is_ascii_graphic())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::descriptor::testutil::fake_property_descriptor;
 
-    use std::{fs, mem::size_of};
+    use std::fs;
 
     /// A valid descriptor that we've pre-generated as test data.
     fn test_contents() -> Vec<u8> {
@@ -89,15 +354,270 @@ mod tests {
         assert!(PropertyDescriptor::new(&test_contents()).is_ok());
     }
 
+    #[test]
+    fn new_property_descriptor_header_is_consistent() {
+        let contents = test_contents();
+        let descriptor = PropertyDescriptor::new(&contents).unwrap();
+        let header = descriptor.header();
+
+        assert_eq!(header.key_num_bytes, descriptor.key.len() as u64);
+        assert_eq!(
+            header.value_num_bytes,
+            (descriptor.value_with_nul.len() - 1) as u64
+        );
+        assert_eq!(
+            header.num_bytes_following as usize,
+            contents.len() - size_of::<avb_bindgen::AvbDescriptor>()
+        );
+    }
+
     #[test]
     fn new_property_descriptor_too_short_header_fails() {
-        let bad_header_size = size_of::<AvbPropertyDescriptor>() - 1;
+        let bad_header_size = HEADER_SIZE - 1;
         assert_eq!(
             PropertyDescriptor::new(&test_contents()[..bad_header_size]).unwrap_err(),
             DescriptorError::InvalidHeader
         );
     }
 
+    #[test]
+    fn new_counting_reports_fixture_length() {
+        let contents = test_contents();
+        let (_, consumed) = PropertyDescriptor::new_counting(&contents).unwrap();
+        assert_eq!(consumed, contents.len());
+    }
+
+    #[test]
+    fn new_property_descriptor_understated_value_length_fails() {
+        let mut contents = fake_property_descriptor(b"key", &[b'v'; 24]);
+        // Shrink `value_num_bytes` (bytes 24..32) by 16; libavb accepts lengths that merely
+        // fit in the body, so only the body length cross-check can catch the mismatch.
+        contents[24..32].copy_from_slice(&8u64.to_be_bytes());
+        assert_eq!(
+            PropertyDescriptor::new(&contents).unwrap_err(),
+            DescriptorError::InvalidSize
+        );
+    }
+
+    #[test]
+    fn new_property_descriptor_invalid_key_utf8_fails() {
+        let contents = fake_property_descriptor(&[0xff, 0xfe], b"value");
+        assert_eq!(
+            PropertyDescriptor::new(&contents).unwrap_err(),
+            DescriptorError::InvalidUtf8
+        );
+    }
+
+    #[test]
+    fn new_property_descriptor_interior_nul_key_fails() {
+        let contents = fake_property_descriptor(b"ke\0y", b"value");
+        assert_eq!(
+            PropertyDescriptor::new(&contents).unwrap_err(),
+            DescriptorError::InvalidNulTermination
+        );
+    }
+
+    #[test]
+    fn key_and_value_offsets_index_into_source_buffer() {
+        let contents = fake_property_descriptor(b"key.one", b"value");
+        let descriptor = PropertyDescriptor::new(&contents).unwrap();
+
+        let key_offset = descriptor.key_offset();
+        assert_eq!(&contents[key_offset..key_offset + 7], b"key.one");
+        let value_offset = descriptor.value_offset();
+        assert_eq!(&contents[value_offset..value_offset + 5], b"value");
+    }
+
+    #[test]
+    fn new_checked_clean_descriptor_has_no_warnings() {
+        let contents = fake_property_descriptor(b"key", b"value");
+        let (_, warnings) = PropertyDescriptor::new_checked(&contents).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn new_checked_flags_empty_key() {
+        let contents = fake_property_descriptor(b"", b"value");
+        let (_, warnings) = PropertyDescriptor::new_checked(&contents).unwrap();
+        assert_eq!(warnings, vec![DescriptorWarning::EmptyKey]);
+    }
+
+    #[test]
+    fn new_checked_flags_all_padding_value() {
+        let contents = fake_property_descriptor(b"key", &[0, 0, 0, 0]);
+        let (_, warnings) = PropertyDescriptor::new_checked(&contents).unwrap();
+        assert_eq!(warnings, vec![DescriptorWarning::AllPaddingValue]);
+    }
+
+    #[test]
+    fn is_conventional_key_accepts_dotted_ascii_keys() {
+        assert!(is_conventional_key("com.android.build.system.fingerprint"));
+        assert!(is_conventional_key("avb"));
+        assert!(is_conventional_key("key_with-punctuation.0"));
+    }
+
+    #[test]
+    fn is_conventional_key_rejects_anomalous_keys() {
+        assert!(!is_conventional_key(""));
+        assert!(!is_conventional_key("key with space"));
+        assert!(!is_conventional_key("key\twith\tcontrol"));
+        assert!(!is_conventional_key("ключ"));
+    }
+
+    #[test]
+    fn semantic_eq_ignores_padding_only_differences() {
+        let contents = fake_property_descriptor(b"key", b"value");
+        // Re-encode the same property with an extra 8-byte padding block.
+        let mut padded = contents.clone();
+        let num_bytes_following = u64::from_be_bytes(padded[8..16].try_into().unwrap()) + 8;
+        padded[8..16].copy_from_slice(&num_bytes_following.to_be_bytes());
+        padded.resize(padded.len() + 8, 0);
+
+        let descriptor = PropertyDescriptor::new(&contents).unwrap();
+        let padded_descriptor = PropertyDescriptor::new(&padded).unwrap();
+        assert!(descriptor.semantic_eq(&padded_descriptor));
+        // Byte-level equality still sees the header difference.
+        assert_ne!(descriptor, padded_descriptor);
+    }
+
+    #[test]
+    fn semantic_eq_detects_value_differences() {
+        let first = fake_property_descriptor(b"key", b"value");
+        let second = fake_property_descriptor(b"key", b"other");
+        assert!(
+            !PropertyDescriptor::new(&first)
+                .unwrap()
+                .semantic_eq(&PropertyDescriptor::new(&second).unwrap())
+        );
+    }
+
+    #[test]
+    fn new_checked_flags_unconventional_key() {
+        let contents = fake_property_descriptor(b"key with space", b"value");
+        let (_, warnings) = PropertyDescriptor::new_checked(&contents).unwrap();
+        assert_eq!(warnings, vec![DescriptorWarning::UnconventionalKey]);
+    }
+
+    #[test]
+    fn content_digest_ignores_trailing_padding() {
+        let contents = fake_property_descriptor(b"key", b"value");
+        // The same descriptor with 8 extra bytes of trailing padding.
+        let mut padded = contents.clone();
+        let num_bytes_following = u64::from_be_bytes(padded[8..16].try_into().unwrap()) + 8;
+        padded[8..16].copy_from_slice(&num_bytes_following.to_be_bytes());
+        padded.resize(padded.len() + 8, 0);
+
+        let descriptor = PropertyDescriptor::new(&contents).unwrap();
+        let padded_descriptor = PropertyDescriptor::new(&padded).unwrap();
+        assert_eq!(descriptor.content_digest(), padded_descriptor.content_digest());
+    }
+
+    #[test]
+    fn content_digest_differs_for_different_values() {
+        let first = fake_property_descriptor(b"key", b"value");
+        let second = fake_property_descriptor(b"key", b"other");
+        assert_ne!(
+            PropertyDescriptor::new(&first).unwrap().content_digest(),
+            PropertyDescriptor::new(&second).unwrap().content_digest()
+        );
+    }
+
+    #[test]
+    fn new_with_limits_at_limits_succeeds() {
+        let contents = fake_property_descriptor(b"abc", b"value");
+        let limits = ParseLimits {
+            max_key: 3,
+            max_value: 5,
+            max_total: contents.len(),
+        };
+        assert!(PropertyDescriptor::new_with_limits(&contents, &limits).is_ok());
+    }
+
+    #[test]
+    fn new_with_limits_oversized_key_fails() {
+        let contents = fake_property_descriptor(b"abcd", b"value");
+        let limits = ParseLimits {
+            max_key: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            PropertyDescriptor::new_with_limits(&contents, &limits).unwrap_err(),
+            DescriptorError::InvalidSize
+        );
+    }
+
+    #[test]
+    fn new_with_limits_oversized_value_fails() {
+        let contents = fake_property_descriptor(b"abc", b"value1");
+        let limits = ParseLimits {
+            max_value: 5,
+            ..Default::default()
+        };
+        assert_eq!(
+            PropertyDescriptor::new_with_limits(&contents, &limits).unwrap_err(),
+            DescriptorError::InvalidSize
+        );
+    }
+
+    #[test]
+    fn new_with_limits_oversized_total_fails() {
+        let contents = fake_property_descriptor(b"abc", b"value");
+        let limits = ParseLimits {
+            max_total: contents.len() - 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            PropertyDescriptor::new_with_limits(&contents, &limits).unwrap_err(),
+            DescriptorError::InvalidSize
+        );
+    }
+
+    /// Builds a descriptor with the given value for testing value classification.
+    fn test_descriptor(value_with_nul: &[u8]) -> PropertyDescriptor {
+        PropertyDescriptor {
+            key: "test.key",
+            key_cstr: CStr::from_bytes_with_nul(b"test.key\0").unwrap(),
+            value_with_nul,
+            header: PropertyDescriptorHeader {
+                tag: 0,
+                num_bytes_following: 0,
+                key_num_bytes: 8,
+                value_num_bytes: (value_with_nul.len() - 1) as u64,
+            },
+        }
+    }
+
+    #[test]
+    fn has_key_matches_exactly() {
+        let descriptor = test_descriptor(b"value\0");
+        assert!(descriptor.has_key("test.key"));
+        assert!(!descriptor.has_key("test.key.suffix"));
+        assert!(!descriptor.has_key("test"));
+    }
+
+    #[test]
+    fn value_is_text_accepts_ascii_and_whitespace() {
+        assert!(test_descriptor(b"plain ascii value\0").value_is_text());
+        assert!(test_descriptor(b"multi\nline\tvalue\0").value_is_text());
+        assert!(test_descriptor(b"\0").value_is_text());
+    }
+
+    #[test]
+    fn value_is_text_rejects_binary() {
+        assert!(!test_descriptor(&[0x01, 0x02, 0x00]).value_is_text());
+        assert!(!test_descriptor(&[0xff, 0xfe, 0x00]).value_is_text());
+    }
+
+    #[test]
+    fn new_property_descriptor_wrong_tag_fails() {
+        // A valid descriptor of a different type must be rejected before sub-type parsing.
+        let hashtree_contents = fs::read("testdata/hashtree_descriptor.bin").unwrap();
+        assert!(matches!(
+            PropertyDescriptor::new(&hashtree_contents).unwrap_err(),
+            DescriptorError::WrongType { .. }
+        ));
+    }
+
     #[test]
     fn new_property_descriptor_too_short_contents_fails() {
         // The last 2 bytes are padding, so we need to drop 3 bytes to trigger an error.
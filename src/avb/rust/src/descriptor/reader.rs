@@ -0,0 +1,161 @@
+// Copyright 2026, The Android Open Source Project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Streaming descriptor reading.
+//!
+//! The `std`-gated counterpart to the slice-based region walkers: descriptors are pulled one
+//! at a time from an `io::Read` source, so very large images can be examined from a file
+//! without mapping the whole descriptor region into memory.
+
+use super::{Descriptor, DescriptorError, DescriptorResult, region};
+use std::io::Read;
+use std::vec::Vec;
+
+/// A descriptor whose encoded bytes are owned rather than borrowed from an image buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedDescriptor {
+    /// The full encoded descriptor, including the generic header and padding.
+    contents: Vec<u8>,
+}
+
+impl OwnedDescriptor {
+    /// Returns the raw encoded descriptor bytes, including the header.
+    pub fn contents(&self) -> &[u8] {
+        &self.contents
+    }
+
+    /// Parses the owned bytes into a typed `Descriptor` borrowing from `self`.
+    pub fn parse(&self) -> DescriptorResult<Descriptor> {
+        Descriptor::parse_with(&self.contents, &[])
+    }
+}
+
+/// Iterator pulling descriptors one at a time from an `io::Read` source.
+///
+/// Each step reads the 16-byte generic header, then exactly the declared body (including
+/// alignment padding). A clean EOF at a descriptor boundary ends iteration; EOF in the
+/// middle of a descriptor surfaces `DescriptorError::InvalidSize`. Any error ends
+/// iteration, since without a trustworthy size the next descriptor can't be located.
+pub struct DescriptorReader<R: Read> {
+    source: R,
+    /// Set once iteration has finished, cleanly or not.
+    done: bool,
+}
+
+impl<R: Read> DescriptorReader<R> {
+    /// Creates a reader pulling descriptors from `source`, which must be positioned at the
+    /// start of a descriptor region.
+    pub fn new(source: R) -> Self {
+        Self { source, done: false }
+    }
+
+    /// Reads the generic descriptor header, or `None` on a clean EOF.
+    fn read_header(&mut self) -> Option<DescriptorResult<[u8; region::GENERIC_HEADER_SIZE]>> {
+        let mut header = [0u8; region::GENERIC_HEADER_SIZE];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.source.read(&mut header[filled..]) {
+                // EOF at the descriptor boundary is the normal end of the region.
+                Ok(0) if filled == 0 => return None,
+                Ok(0) => return Some(Err(DescriptorError::InvalidSize)),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => return Some(Err(DescriptorError::InvalidContents)),
+            }
+        }
+        Some(Ok(header))
+    }
+}
+
+impl<R: Read> Iterator for DescriptorReader<R> {
+    type Item = DescriptorResult<OwnedDescriptor>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let header = match self.read_header()? {
+            Ok(header) => header,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+        // Validates the header fields (alignment, overflow) and gives the total size.
+        let total_size = match region::peek_descriptor_header(&header) {
+            Ok((_, total_size)) => total_size,
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+
+        let mut contents = Vec::with_capacity(total_size);
+        contents.extend_from_slice(&header);
+        contents.resize(total_size, 0);
+        if let Err(e) = self.source.read_exact(&mut contents[header.len()..]) {
+            self.done = true;
+            let error = match e.kind() {
+                std::io::ErrorKind::UnexpectedEof => DescriptorError::InvalidSize,
+                _ => DescriptorError::InvalidContents,
+            };
+            return Some(Err(error));
+        }
+        Some(Ok(OwnedDescriptor { contents }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::testutil::fake_descriptor;
+    use std::io::Cursor;
+
+    #[test]
+    fn reader_yields_both_descriptors_then_ends() {
+        let first = fake_descriptor(0x42);
+        let second = fake_descriptor(0x43);
+        let mut region = first.clone();
+        region.extend_from_slice(&second);
+
+        let mut reader = DescriptorReader::new(Cursor::new(region));
+        assert_eq!(reader.next().unwrap().unwrap().contents(), &first[..]);
+        assert_eq!(reader.next().unwrap().unwrap().contents(), &second[..]);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn reader_truncated_body_yields_invalid_size() {
+        let descriptor = fake_descriptor(0x42);
+        let truncated = &descriptor[..descriptor.len() - 3];
+
+        let mut reader = DescriptorReader::new(Cursor::new(truncated.to_vec()));
+        assert_eq!(reader.next(), Some(Err(DescriptorError::InvalidSize)));
+        assert_eq!(reader.next(), None);
+    }
+
+    #[test]
+    fn reader_truncated_header_yields_invalid_size() {
+        let mut reader = DescriptorReader::new(Cursor::new(vec![0u8; 8]));
+        assert_eq!(reader.next(), Some(Err(DescriptorError::InvalidSize)));
+        assert_eq!(reader.next(), None);
+    }
+
+    #[test]
+    fn owned_descriptor_parses_as_unknown() {
+        let mut reader = DescriptorReader::new(Cursor::new(fake_descriptor(0x42)));
+        let owned = reader.next().unwrap().unwrap();
+        assert!(matches!(owned.parse(), Ok(Descriptor::Unknown(_))));
+    }
+}
@@ -0,0 +1,907 @@
+// Copyright 2026, The Android Open Source Project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Raw descriptor region handling.
+//!
+//! These utilities operate directly on the encoded descriptor region bytes without going
+//! through libavb's `avb_descriptor_foreach()`, which allows early-exit queries that don't
+//! need to decode every descriptor.
+
+use super::{DescriptorError, DescriptorResult, PropertyDescriptor, property, util::split_slice};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use avb_bindgen::{
+    AvbDescriptor, AvbDescriptorTag, AvbSHA256Ctx, avb_sha256_final, avb_sha256_init,
+    avb_sha256_update,
+};
+use core::mem::size_of;
+
+/// Size in bytes of the generic descriptor header (tag + num_bytes_following).
+pub(super) const GENERIC_HEADER_SIZE: usize = size_of::<AvbDescriptor>();
+
+/// Reads the generic big-endian header at the front of `region`.
+///
+/// # Arguments
+/// * `region`: raw descriptor region bytes, starting at a descriptor header.
+///
+/// # Returns
+/// A `(tag, total_size)` tuple where `total_size` includes the header, or `DescriptorError`
+/// if the header is truncated or its size is not 8-byte aligned as libavb requires.
+pub(super) fn peek_descriptor_header(region: &[u8]) -> DescriptorResult<(u64, usize)> {
+    let (header, _) = split_slice(region, GENERIC_HEADER_SIZE)
+        .map_err(|_| DescriptorError::InvalidHeader)?;
+    // We can always unwrap here because `split_slice()` guarantees 16 bytes.
+    let tag = u64::from_be_bytes(header[..8].try_into().unwrap());
+    let num_bytes_following = u64::from_be_bytes(header[8..].try_into().unwrap());
+    // libavb requires all descriptors to be a multiple of 8 bytes.
+    if num_bytes_following % 8 != 0 {
+        return Err(DescriptorError::InvalidHeader);
+    }
+    let num_bytes_following: usize = num_bytes_following
+        .try_into()
+        .map_err(|_| DescriptorError::InvalidValue)?;
+    let total_size = GENERIC_HEADER_SIZE
+        .checked_add(num_bytes_following)
+        .ok_or(DescriptorError::InvalidValue)?;
+    Ok((tag, total_size))
+}
+
+/// Locates the first descriptor with the given tag in a descriptor region.
+///
+/// Walks the region header-by-header without decoding descriptor bodies, stopping as soon as
+/// a match is found. The returned slice includes the descriptor header so it can be handed
+/// directly to the appropriate typed parser.
+///
+/// # Arguments
+/// * `region`: raw descriptor region bytes.
+/// * `tag`: the `AvbDescriptorTag` value to search for.
+///
+/// # Returns
+/// The raw contents slice of the first matching descriptor, `Ok(None)` if the region contains
+/// no such descriptor, or `DescriptorError` if a malformed header was encountered first.
+pub fn find_descriptor_by_tag(region: &[u8], tag: u64) -> DescriptorResult<Option<&[u8]>> {
+    let mut remaining = region;
+    while !remaining.is_empty() {
+        let (current_tag, total_size) = peek_descriptor_header(remaining)?;
+        let (contents, rest) = split_slice(remaining, total_size)?;
+        if current_tag == tag {
+            return Ok(Some(contents));
+        }
+        remaining = rest;
+    }
+    Ok(None)
+}
+
+/// Byte-usage breakdown of the property descriptors in a region.
+///
+/// Useful for image-size budgeting: it shows how much of the encoded region is spent on
+/// headers, keys, values, and alignment padding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegionStats {
+    /// Bytes spent on property descriptor headers.
+    pub header_bytes: usize,
+    /// Bytes spent on property keys, including nul terminators.
+    pub key_bytes: usize,
+    /// Bytes spent on property values, including nul terminators.
+    pub value_bytes: usize,
+    /// Bytes of 8-byte alignment padding in property descriptors.
+    pub padding_bytes: usize,
+    /// Number of property descriptors counted.
+    pub property_count: usize,
+    /// Bytes occupied by non-property descriptors, which are not broken down further.
+    pub other_bytes: usize,
+}
+
+/// Walks a descriptor region and sums per-category byte usage of its property descriptors.
+///
+/// # Arguments
+/// * `region`: raw descriptor region bytes.
+///
+/// # Returns
+/// The accumulated `RegionStats`, or `DescriptorError` if the region is malformed.
+pub fn region_stats(region: &[u8]) -> DescriptorResult<RegionStats> {
+    let mut stats = RegionStats::default();
+    let mut remaining = region;
+    while !remaining.is_empty() {
+        let (tag, total_size) = peek_descriptor_header(remaining)?;
+        let (contents, rest) = split_slice(remaining, total_size)?;
+        if tag == AvbDescriptorTag::AVB_DESCRIPTOR_TAG_PROPERTY as u64 {
+            let descriptor = PropertyDescriptor::new(contents)?;
+            let header = descriptor.header();
+            let key_bytes = header.key_num_bytes as usize + 1;
+            let value_bytes = header.value_num_bytes as usize + 1;
+            stats.header_bytes += property::HEADER_SIZE;
+            stats.key_bytes += key_bytes;
+            stats.value_bytes += value_bytes;
+            stats.padding_bytes +=
+                total_size.saturating_sub(property::HEADER_SIZE + key_bytes + value_bytes);
+            stats.property_count += 1;
+        } else {
+            stats.other_bytes += total_size;
+        }
+        remaining = rest;
+    }
+    Ok(stats)
+}
+
+/// Re-encodes a whole descriptor region from a list of individually encoded descriptors.
+///
+/// This is the complement of the region walkers above: each input must start with a generic
+/// descriptor header, and bodies that are not yet padded to the required 8-byte multiple are
+/// zero-padded with `num_bytes_following` updated to match. The resulting region walks
+/// cleanly with `find_descriptor_by_tag()` and friends.
+///
+/// # Arguments
+/// * `descriptors`: the encoded descriptors (header + body each) to concatenate.
+///
+/// # Returns
+/// The encoded region, or `DescriptorError` if any input is too short to hold a header.
+pub fn encode_region(descriptors: &[&[u8]]) -> DescriptorResult<Vec<u8>> {
+    let mut region = Vec::new();
+    for descriptor in descriptors {
+        let (header, body) =
+            split_slice(descriptor, GENERIC_HEADER_SIZE).map_err(|_| DescriptorError::InvalidHeader)?;
+        // We can always unwrap here because `split_slice()` guarantees 16 bytes.
+        let tag = u64::from_be_bytes(header[..8].try_into().unwrap());
+        let padded_len = body.len().next_multiple_of(8);
+
+        region.extend_from_slice(&tag.to_be_bytes());
+        region.extend_from_slice(&(padded_len as u64).to_be_bytes());
+        region.extend_from_slice(body);
+        region.resize(region.len() + (padded_len - body.len()), 0);
+    }
+    Ok(region)
+}
+
+/// Overwrites one property's value bytes within an encoded region.
+///
+/// Build tooling that only needs to swap a same-length value (e.g. bump a version string)
+/// can patch the region in place instead of fully re-encoding it. The trailing nul and all
+/// padding stay untouched, so the region remains valid without re-walking it.
+///
+/// # Arguments
+/// * `region`: raw descriptor region bytes to patch.
+/// * `key`: key of the property whose value is replaced.
+/// * `new_value`: replacement value, which must have exactly the current value's length.
+///
+/// # Returns
+/// `Ok(())` after patching, `DescriptorError::InvalidSize` if `new_value` has a different
+/// length (meaning the caller must re-encode instead), `DescriptorError::InvalidContents`
+/// if the region holds no property with `key`, or another `DescriptorError` if the region
+/// is malformed.
+pub fn set_property_value_inplace(
+    region: &mut [u8],
+    key: &str,
+    new_value: &[u8],
+) -> DescriptorResult<()> {
+    let mut offset = 0;
+    while offset < region.len() {
+        let (tag, total_size) = peek_descriptor_header(&region[offset..])?;
+        let (contents, _) = split_slice(&region[offset..], total_size)?;
+        if tag == AvbDescriptorTag::AVB_DESCRIPTOR_TAG_PROPERTY as u64 {
+            let descriptor = PropertyDescriptor::new(contents)?;
+            if descriptor.has_key(key) {
+                let value_len = descriptor.value_with_nul.len() - 1;
+                if new_value.len() != value_len {
+                    return Err(DescriptorError::InvalidSize);
+                }
+                let value_offset = offset + descriptor.value_offset();
+                region[value_offset..value_offset + value_len].copy_from_slice(new_value);
+                return Ok(());
+            }
+        }
+        offset += total_size;
+    }
+    Err(DescriptorError::InvalidContents)
+}
+
+/// How `merge_regions()` resolves two property descriptors with the same key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// The overlay's value replaces the base's value.
+    OverlayWins,
+    /// The base's value is kept and the overlay's is dropped.
+    BaseWins,
+    /// A duplicate key is an error.
+    ErrorOnConflict,
+}
+
+/// Splits a region into per-descriptor slices, with the property key where applicable.
+fn collect_entries(region: &[u8]) -> DescriptorResult<Vec<(&[u8], Option<&str>)>> {
+    let mut entries = Vec::new();
+    let mut remaining = region;
+    while !remaining.is_empty() {
+        let (tag, total_size) = peek_descriptor_header(remaining)?;
+        let (contents, rest) = split_slice(remaining, total_size)?;
+        let key = if tag == AvbDescriptorTag::AVB_DESCRIPTOR_TAG_PROPERTY as u64 {
+            Some(PropertyDescriptor::new(contents)?.key)
+        } else {
+            None
+        };
+        entries.push((contents, key));
+        remaining = rest;
+    }
+    Ok(entries)
+}
+
+/// Merges a base descriptor region with an overlay.
+///
+/// Property descriptors are deduplicated by key with `policy` deciding which side wins;
+/// non-property descriptors are concatenated from both regions unchanged. Base descriptors
+/// keep their relative order, followed by overlay descriptors that survived the merge.
+///
+/// # Arguments
+/// * `base`: raw descriptor region bytes of the base image.
+/// * `overlay`: raw descriptor region bytes to merge on top.
+/// * `policy`: duplicate-key resolution policy.
+///
+/// # Returns
+/// The merged, re-encoded region, `DescriptorError::InvalidContents` on a duplicate key
+/// under `MergePolicy::ErrorOnConflict`, or another `DescriptorError` if either region is
+/// malformed.
+pub fn merge_regions(
+    base: &[u8],
+    overlay: &[u8],
+    policy: MergePolicy,
+) -> DescriptorResult<Vec<u8>> {
+    let base_entries = collect_entries(base)?;
+    let overlay_entries = collect_entries(overlay)?;
+
+    let mut merged = Vec::new();
+    for (contents, key) in &base_entries {
+        let conflicts = key
+            .is_some_and(|key| overlay_entries.iter().any(|(_, other)| *other == Some(key)));
+        if conflicts {
+            match policy {
+                MergePolicy::OverlayWins => continue, // The overlay's copy is emitted below.
+                MergePolicy::BaseWins => {}
+                MergePolicy::ErrorOnConflict => return Err(DescriptorError::InvalidContents),
+            }
+        }
+        merged.push(*contents);
+    }
+    for (contents, key) in &overlay_entries {
+        let conflicts = key
+            .is_some_and(|key| base_entries.iter().any(|(_, other)| *other == Some(key)));
+        if conflicts && policy == MergePolicy::BaseWins {
+            continue;
+        }
+        merged.push(*contents);
+    }
+    encode_region(&merged)
+}
+
+/// Computes the total encoded length of the first `count` descriptors in a region.
+///
+/// Walks `count` descriptor headers summing their aligned sizes (header, body, padding),
+/// so the result can be cross-checked against the vbmeta header's declared
+/// `descriptors_size` to catch header/body mismatches in malformed images.
+///
+/// # Arguments
+/// * `region`: raw descriptor region bytes, starting at the first descriptor.
+/// * `count`: number of descriptors to walk, from the vbmeta header.
+///
+/// # Returns
+/// The total bytes the `count` descriptors consume, or `DescriptorError` if a header is
+/// malformed or the region is too short to hold them.
+pub fn region_encoded_len(region: &[u8], count: usize) -> DescriptorResult<usize> {
+    let mut offset = 0;
+    for _ in 0..count {
+        let (_, total_size) = peek_descriptor_header(&region[offset..])?;
+        offset = offset
+            .checked_add(total_size)
+            .ok_or(DescriptorError::InvalidValue)?;
+        if offset > region.len() {
+            return Err(DescriptorError::InvalidSize);
+        }
+    }
+    Ok(offset)
+}
+
+/// Rewrites a region into a canonical descriptor order for reproducible images.
+///
+/// Property descriptors come first, sorted by key with the value as tie-break for
+/// duplicate keys; non-property descriptors follow in their original relative order.
+/// Descriptor bytes are copied unchanged, so the output walks and parses exactly like the
+/// input. Two regions holding the same descriptors in different orders canonicalize to
+/// identical bytes.
+///
+/// # Arguments
+/// * `region`: raw descriptor region bytes.
+///
+/// # Returns
+/// The re-encoded canonical region, or `DescriptorError` if the region is malformed.
+pub fn canonicalize_region(region: &[u8]) -> DescriptorResult<Vec<u8>> {
+    let entries = collect_entries(region)?;
+    let mut properties = Vec::new();
+    let mut others = Vec::new();
+    for (contents, key) in &entries {
+        match key {
+            Some(_) => {
+                let descriptor = PropertyDescriptor::new(contents)?;
+                properties.push((descriptor.key, descriptor.value_with_nul, *contents));
+            }
+            None => others.push(*contents),
+        }
+    }
+    properties.sort_by(|a, b| a.0.cmp(b.0).then(a.1.cmp(b.1)));
+    let ordered: Vec<&[u8]> = properties
+        .iter()
+        .map(|(_, _, contents)| *contents)
+        .chain(others)
+        .collect();
+    encode_region(&ordered)
+}
+
+/// Reports where a region first stops being valid.
+///
+/// Walks the region descriptor by descriptor and returns the byte offset of the first
+/// descriptor that fails to parse — a malformed or truncated header, a body that exceeds
+/// the remaining data, or an undecodable property body. Repair tooling wants the offset of
+/// the damage rather than a bare error.
+///
+/// # Arguments
+/// * `region`: raw descriptor region bytes.
+///
+/// # Returns
+/// The offset where parsing first fails, or `None` if the whole region is valid.
+pub fn first_invalid_offset(region: &[u8]) -> Option<usize> {
+    let mut offset = 0;
+    while offset < region.len() {
+        let Ok((tag, total_size)) = peek_descriptor_header(&region[offset..]) else {
+            return Some(offset);
+        };
+        let Ok((contents, _)) = split_slice(&region[offset..], total_size) else {
+            return Some(offset);
+        };
+        if tag == AvbDescriptorTag::AVB_DESCRIPTOR_TAG_PROPERTY as u64
+            && PropertyDescriptor::new(contents).is_err()
+        {
+            return Some(offset);
+        }
+        offset += total_size;
+    }
+    None
+}
+
+/// An owned copy of one property descriptor's key and value, detached from the region
+/// bytes it was parsed from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedProperty {
+    /// The property key.
+    pub key: String,
+    /// The property value, excluding the trailing nul.
+    pub value: Vec<u8>,
+}
+
+impl OwnedProperty {
+    /// Builds a property with a string value.
+    pub fn from_key_str(key: &str, value: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            value: value.as_bytes().to_vec(),
+        }
+    }
+
+    /// Builds a property with a `u64` value encoded as decimal ASCII, matching how avbtool
+    /// stores numeric properties.
+    pub fn from_key_u64(key: &str, value: u64) -> Self {
+        Self::from_key_str(key, &value.to_string())
+    }
+
+    /// Builds a property with a boolean value encoded as ASCII `true`/`false`.
+    pub fn from_key_bool(key: &str, value: bool) -> Self {
+        Self::from_key_str(key, if value { "true" } else { "false" })
+    }
+
+    /// Parses the value as ASCII text into `T`, e.g. `value_parse::<u64>()` to recover a
+    /// number stored by `from_key_u64()`. Returns `None` for non-UTF-8 or non-parsing
+    /// values.
+    pub fn value_parse<T: core::str::FromStr>(&self) -> Option<T> {
+        core::str::from_utf8(&self.value).ok()?.parse().ok()
+    }
+
+    /// Encodes the property as raw descriptor bytes: generic header, key, nul, value, nul,
+    /// zero padding to the 8-byte alignment libavb requires.
+    pub fn encode(&self) -> Vec<u8> {
+        let body_len = self.key.len() + 1 + self.value.len() + 1;
+        let num_bytes_following = 2 * size_of::<u64>() + body_len.next_multiple_of(8);
+
+        let mut bytes = Vec::with_capacity(GENERIC_HEADER_SIZE + num_bytes_following);
+        bytes.extend_from_slice(
+            &(AvbDescriptorTag::AVB_DESCRIPTOR_TAG_PROPERTY as u64).to_be_bytes(),
+        );
+        bytes.extend_from_slice(&(num_bytes_following as u64).to_be_bytes());
+        bytes.extend_from_slice(&(self.key.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&(self.value.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(self.key.as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(&self.value);
+        bytes.push(0);
+        bytes.resize(GENERIC_HEADER_SIZE + num_bytes_following, 0);
+        bytes
+    }
+}
+
+/// Parses a region's property descriptors, rejecting duplicate keys.
+///
+/// The default parsers are permissive about duplicate keys since libavb tolerates them,
+/// but an image generator that should emit unique keys wants duplicates caught at parse
+/// time. Non-property descriptors are walked over and not returned.
+///
+/// # Arguments
+/// * `region`: raw descriptor region bytes.
+///
+/// # Returns
+/// The properties in region order, `DescriptorError::DuplicateKey` naming the offending
+/// key if two properties share one, or another `DescriptorError` if the region is
+/// malformed.
+pub fn parse_region_unique(region: &[u8]) -> DescriptorResult<Vec<OwnedProperty>> {
+    let mut properties = Vec::<OwnedProperty>::new();
+    let mut remaining = region;
+    while !remaining.is_empty() {
+        let (tag, total_size) = peek_descriptor_header(remaining)?;
+        let (contents, rest) = split_slice(remaining, total_size)?;
+        if tag == AvbDescriptorTag::AVB_DESCRIPTOR_TAG_PROPERTY as u64 {
+            let descriptor = PropertyDescriptor::new(contents)?;
+            if properties.iter().any(|p| p.key == descriptor.key) {
+                return Err(DescriptorError::DuplicateKey(descriptor.key.to_string()));
+            }
+            properties.push(OwnedProperty {
+                key: descriptor.key.to_string(),
+                value: descriptor.value_with_nul[..descriptor.value_with_nul.len() - 1]
+                    .to_vec(),
+            });
+        }
+        remaining = rest;
+    }
+    Ok(properties)
+}
+
+/// Parses a region and computes a SHA-256 over the exact bytes walked, in one pass.
+///
+/// Validating a region against a signed digest otherwise takes two passes (hash, then
+/// parse) with no guarantee they cover the same bytes. Here each descriptor's bytes are fed
+/// to the hash as they are split off the region, so the digest covers precisely what was
+/// parsed. Non-property descriptors contribute to the digest but, as in
+/// `parse_region_unique()`, are not returned.
+///
+/// # Arguments
+/// * `region`: raw descriptor region bytes.
+///
+/// # Returns
+/// The properties in region order together with the SHA-256 digest of the walked bytes, or
+/// `DescriptorError` if the region is malformed.
+pub fn parse_and_digest(region: &[u8]) -> DescriptorResult<(Vec<OwnedProperty>, [u8; 32])> {
+    let mut properties = Vec::<OwnedProperty>::new();
+    let mut ctx = core::mem::MaybeUninit::<AvbSHA256Ctx>::uninit();
+    // SAFETY: `avb_sha256_init()` fully initializes the context before any update uses it.
+    unsafe { avb_sha256_init(ctx.as_mut_ptr()) };
+    let mut remaining = region;
+    while !remaining.is_empty() {
+        let (tag, total_size) = peek_descriptor_header(remaining)?;
+        let (contents, rest) = split_slice(remaining, total_size)?;
+        // SAFETY: `contents` outlives the call and its length is passed alongside it.
+        unsafe { avb_sha256_update(ctx.as_mut_ptr(), contents.as_ptr(), contents.len()) };
+        if tag == AvbDescriptorTag::AVB_DESCRIPTOR_TAG_PROPERTY as u64 {
+            let descriptor = PropertyDescriptor::new(contents)?;
+            properties.push(OwnedProperty {
+                key: descriptor.key.to_string(),
+                value: descriptor.value_with_nul[..descriptor.value_with_nul.len() - 1].to_vec(),
+            });
+        }
+        remaining = rest;
+    }
+    let mut digest = [0u8; 32];
+    // SAFETY: `avb_sha256_final()` returns a pointer to the 32-byte digest inside `ctx`,
+    // which remains valid until `ctx` is dropped after the copy.
+    unsafe {
+        let digest_ptr = avb_sha256_final(ctx.as_mut_ptr());
+        digest.copy_from_slice(core::slice::from_raw_parts(digest_ptr, digest.len()));
+    }
+    Ok((properties, digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::testutil::{fake_descriptor, fake_property_descriptor};
+    use alloc::string::{String, ToString};
+    use avb_bindgen::AvbDescriptorTag;
+
+    #[test]
+    fn find_descriptor_by_tag_in_mixed_region_succeeds() {
+        let property_tag = AvbDescriptorTag::AVB_DESCRIPTOR_TAG_PROPERTY as u64;
+        let mut region = fake_descriptor(0x42);
+        let property = fake_descriptor(property_tag);
+        region.extend_from_slice(&property);
+
+        let found = find_descriptor_by_tag(&region, property_tag).unwrap();
+        assert_eq!(found, Some(&property[..]));
+    }
+
+    #[test]
+    fn find_descriptor_by_tag_missing_returns_none() {
+        let region = fake_descriptor(0x42);
+        assert_eq!(find_descriptor_by_tag(&region, 0x43).unwrap(), None);
+    }
+
+    #[test]
+    fn region_stats_two_property_region() {
+        // Bodies are 8 and 14 bytes before padding, so padding is 0 and 2 bytes.
+        let first = fake_property_descriptor(b"abc", b"def");
+        let second = fake_property_descriptor(b"key.one", b"value");
+        let mut region = first;
+        region.extend_from_slice(&second);
+        region.extend_from_slice(&fake_descriptor(0x42));
+
+        let stats = region_stats(&region).unwrap();
+        assert_eq!(
+            stats,
+            RegionStats {
+                header_bytes: 2 * property::HEADER_SIZE,
+                key_bytes: 4 + 8,
+                value_bytes: 4 + 6,
+                padding_bytes: 2,
+                property_count: 2,
+                other_bytes: GENERIC_HEADER_SIZE + 8,
+            }
+        );
+    }
+
+    #[test]
+    fn region_stats_empty_region() {
+        assert_eq!(region_stats(&[]).unwrap(), RegionStats::default());
+    }
+
+    #[test]
+    fn encode_region_round_trips_through_walker() {
+        let first = fake_descriptor(0x42);
+        let second = fake_descriptor(0x43);
+        let region = encode_region(&[&first, &second]).unwrap();
+
+        assert_eq!(
+            find_descriptor_by_tag(&region, 0x42).unwrap(),
+            Some(&first[..])
+        );
+        assert_eq!(
+            find_descriptor_by_tag(&region, 0x43).unwrap(),
+            Some(&second[..])
+        );
+    }
+
+    #[test]
+    fn encode_region_pads_unaligned_body() {
+        // A descriptor with a 3-byte body; `encode_region()` should pad it out to 8 bytes.
+        let mut unpadded = Vec::new();
+        unpadded.extend_from_slice(&0x42u64.to_be_bytes());
+        unpadded.extend_from_slice(&3u64.to_be_bytes());
+        unpadded.extend_from_slice(&[1, 2, 3]);
+
+        let region = encode_region(&[&unpadded]).unwrap();
+        assert_eq!(region.len(), GENERIC_HEADER_SIZE + 8);
+        assert_eq!(
+            peek_descriptor_header(&region).unwrap(),
+            (0x42, GENERIC_HEADER_SIZE + 8)
+        );
+        assert_eq!(&region[GENERIC_HEADER_SIZE..], &[1, 2, 3, 0, 0, 0, 0, 0]);
+    }
+
+    /// Collects the `(key, value)` pairs of the property descriptors in a region.
+    fn property_pairs(region: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut pairs = Vec::new();
+        let mut remaining = region;
+        while !remaining.is_empty() {
+            let (tag, total_size) = peek_descriptor_header(remaining).unwrap();
+            let (contents, rest) = split_slice(remaining, total_size).unwrap();
+            if tag == AvbDescriptorTag::AVB_DESCRIPTOR_TAG_PROPERTY as u64 {
+                let descriptor = PropertyDescriptor::new(contents).unwrap();
+                let value = &descriptor.value_with_nul[..descriptor.value_with_nul.len() - 1];
+                pairs.push((descriptor.key.to_string(), value.to_vec()));
+            }
+            remaining = rest;
+        }
+        pairs
+    }
+
+    #[test]
+    fn merge_regions_overlay_wins_replaces_value() {
+        let base = fake_property_descriptor(b"shared", b"base");
+        let mut overlay = fake_property_descriptor(b"shared", b"overlay");
+        overlay.extend_from_slice(&fake_property_descriptor(b"extra", b"1"));
+
+        let merged = merge_regions(&base, &overlay, MergePolicy::OverlayWins).unwrap();
+        assert_eq!(
+            property_pairs(&merged),
+            vec![
+                ("shared".to_string(), b"overlay".to_vec()),
+                ("extra".to_string(), b"1".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_regions_base_wins_keeps_value() {
+        let base = fake_property_descriptor(b"shared", b"base");
+        let overlay = fake_property_descriptor(b"shared", b"overlay");
+
+        let merged = merge_regions(&base, &overlay, MergePolicy::BaseWins).unwrap();
+        assert_eq!(
+            property_pairs(&merged),
+            vec![("shared".to_string(), b"base".to_vec())]
+        );
+    }
+
+    #[test]
+    fn merge_regions_error_on_conflict_fails() {
+        let base = fake_property_descriptor(b"shared", b"base");
+        let overlay = fake_property_descriptor(b"shared", b"overlay");
+
+        assert_eq!(
+            merge_regions(&base, &overlay, MergePolicy::ErrorOnConflict).unwrap_err(),
+            DescriptorError::InvalidContents
+        );
+    }
+
+    #[test]
+    fn merge_regions_concatenates_non_property_descriptors() {
+        let mut base = fake_descriptor(0x42);
+        base.extend_from_slice(&fake_property_descriptor(b"key", b"1"));
+        let overlay = fake_descriptor(0x43);
+
+        let merged = merge_regions(&base, &overlay, MergePolicy::ErrorOnConflict).unwrap();
+        assert!(find_descriptor_by_tag(&merged, 0x42).unwrap().is_some());
+        assert!(find_descriptor_by_tag(&merged, 0x43).unwrap().is_some());
+        assert_eq!(property_pairs(&merged).len(), 1);
+    }
+
+    #[test]
+    fn encode_region_truncated_header_fails() {
+        assert_eq!(
+            encode_region(&[&[0u8; 8]]).unwrap_err(),
+            DescriptorError::InvalidHeader
+        );
+    }
+
+    #[test]
+    fn region_encoded_len_two_descriptor_region() {
+        let first = fake_property_descriptor(b"key", b"value");
+        let second = fake_descriptor(0x42);
+        let mut region = first.clone();
+        region.extend_from_slice(&second);
+        // Trailing bytes past the counted descriptors are not included.
+        region.extend_from_slice(&[0xff; 8]);
+
+        assert_eq!(region_encoded_len(&region, 0).unwrap(), 0);
+        assert_eq!(region_encoded_len(&region, 1).unwrap(), first.len());
+        assert_eq!(
+            region_encoded_len(&region, 2).unwrap(),
+            first.len() + second.len()
+        );
+    }
+
+    #[test]
+    fn region_encoded_len_truncated_region_fails() {
+        let region = fake_property_descriptor(b"key", b"value");
+        assert_eq!(
+            region_encoded_len(&region[..region.len() - 1], 1).unwrap_err(),
+            DescriptorError::InvalidSize
+        );
+        // Asking for more descriptors than the region holds is also an error.
+        assert!(region_encoded_len(&region, 2).is_err());
+    }
+
+    #[test]
+    fn owned_property_builders_round_trip_through_value_parse() {
+        assert_eq!(
+            OwnedProperty::from_key_u64("key", 4096).value_parse::<u64>(),
+            Some(4096)
+        );
+        assert_eq!(
+            OwnedProperty::from_key_bool("key", true).value_parse::<bool>(),
+            Some(true)
+        );
+        assert_eq!(
+            OwnedProperty::from_key_str("key", "value").value_parse::<String>(),
+            Some("value".to_string())
+        );
+        assert_eq!(OwnedProperty::from_key_str("key", "value").value_parse::<u64>(), None);
+    }
+
+    #[test]
+    fn owned_property_encode_matches_reference_encoding() {
+        let property = OwnedProperty::from_key_u64("key", 42);
+        assert_eq!(property.encode(), fake_property_descriptor(b"key", b"42"));
+        // The encoding parses back to the same property.
+        assert_eq!(parse_region_unique(&property.encode()).unwrap(), vec![property]);
+    }
+
+    #[test]
+    fn canonicalize_region_orders_independently_of_input() {
+        let property_a = fake_property_descriptor(b"a.key", b"1");
+        let property_b = fake_property_descriptor(b"b.key", b"2");
+        let other = fake_descriptor(0x42);
+
+        let mut first = property_b.clone();
+        first.extend_from_slice(&other);
+        first.extend_from_slice(&property_a);
+        let mut second = property_a.clone();
+        second.extend_from_slice(&property_b);
+        second.extend_from_slice(&other);
+
+        let canonical = canonicalize_region(&first).unwrap();
+        assert_eq!(canonical, canonicalize_region(&second).unwrap());
+        // Properties sorted by key come first, then the non-property descriptor.
+        let mut expected = property_a;
+        expected.extend_from_slice(&property_b);
+        expected.extend_from_slice(&other);
+        assert_eq!(canonical, expected);
+        // The canonical output still walks cleanly.
+        assert_eq!(first_invalid_offset(&canonical), None);
+    }
+
+    #[test]
+    fn canonicalize_region_breaks_key_ties_by_value() {
+        let low = fake_property_descriptor(b"key", b"aaa");
+        let high = fake_property_descriptor(b"key", b"bbb");
+        let mut region = high.clone();
+        region.extend_from_slice(&low);
+
+        let mut expected = low;
+        expected.extend_from_slice(&high);
+        assert_eq!(canonicalize_region(&region).unwrap(), expected);
+    }
+
+    #[test]
+    fn first_invalid_offset_valid_region_returns_none() {
+        let mut region = fake_property_descriptor(b"key", b"value");
+        region.extend_from_slice(&fake_descriptor(0x42));
+        assert_eq!(first_invalid_offset(&region), None);
+        assert_eq!(first_invalid_offset(&[]), None);
+    }
+
+    #[test]
+    fn first_invalid_offset_reports_truncated_second_descriptor() {
+        let first = fake_property_descriptor(b"key", b"value");
+        let second_start = first.len();
+        let mut region = first;
+        let second = fake_descriptor(0x42);
+        // Drop the second descriptor's last body byte so its claimed size overruns.
+        region.extend_from_slice(&second[..second.len() - 1]);
+        assert_eq!(first_invalid_offset(&region), Some(second_start));
+    }
+
+    #[test]
+    fn parse_region_unique_returns_properties_in_order() {
+        let mut region = fake_property_descriptor(b"key.one", b"first");
+        region.extend_from_slice(&fake_descriptor(0x42));
+        region.extend_from_slice(&fake_property_descriptor(b"key.two", b"second"));
+
+        let properties = parse_region_unique(&region).unwrap();
+        assert_eq!(
+            properties,
+            vec![
+                OwnedProperty {
+                    key: "key.one".to_string(),
+                    value: b"first".to_vec(),
+                },
+                OwnedProperty {
+                    key: "key.two".to_string(),
+                    value: b"second".to_vec(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_region_unique_duplicate_key_fails() {
+        let mut region = fake_property_descriptor(b"key", b"first");
+        region.extend_from_slice(&fake_property_descriptor(b"key", b"second"));
+        assert_eq!(
+            parse_region_unique(&region).unwrap_err(),
+            DescriptorError::DuplicateKey("key".to_string())
+        );
+    }
+
+    /// Independently computes a SHA-256 for comparison against `parse_and_digest()`.
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        let mut digest = [0u8; 32];
+        let mut ctx = core::mem::MaybeUninit::<AvbSHA256Ctx>::uninit();
+        // SAFETY: same contract as in `parse_and_digest()`: the context is initialized
+        // before use and the final digest is copied out before `ctx` is dropped.
+        unsafe {
+            avb_sha256_init(ctx.as_mut_ptr());
+            avb_sha256_update(ctx.as_mut_ptr(), data.as_ptr(), data.len());
+            let digest_ptr = avb_sha256_final(ctx.as_mut_ptr());
+            digest.copy_from_slice(core::slice::from_raw_parts(digest_ptr, digest.len()));
+        }
+        digest
+    }
+
+    #[test]
+    fn parse_and_digest_matches_independent_sha() {
+        let mut region = fake_property_descriptor(b"key", b"value");
+        region.extend_from_slice(&fake_descriptor(0x42));
+
+        let (properties, digest) = parse_and_digest(&region).unwrap();
+
+        assert_eq!(properties.len(), 1);
+        assert_eq!(properties[0].key, "key");
+        assert_eq!(properties[0].value, b"value");
+        // The walk consumes the whole region, so the digest must cover exactly its bytes.
+        assert_eq!(digest, sha256(&region));
+    }
+
+    #[test]
+    fn parse_and_digest_empty_region_digests_nothing() {
+        let (properties, digest) = parse_and_digest(&[]).unwrap();
+        assert!(properties.is_empty());
+        assert_eq!(digest, sha256(&[]));
+    }
+
+    #[test]
+    fn parse_and_digest_truncated_region_fails() {
+        let region = fake_descriptor(0x42);
+        assert_eq!(
+            parse_and_digest(&region[..region.len() - 1]).unwrap_err(),
+            DescriptorError::InvalidSize
+        );
+    }
+
+    #[test]
+    fn set_property_value_inplace_rewrites_value() {
+        let mut region = fake_descriptor(0x42);
+        region.extend_from_slice(&fake_property_descriptor(b"key.one", b"old"));
+        region.extend_from_slice(&fake_property_descriptor(b"key.two", b"other"));
+
+        set_property_value_inplace(&mut region, "key.one", b"new").unwrap();
+
+        let pairs = property_pairs(&region);
+        assert_eq!(pairs[0], ("key.one".to_string(), "new".to_string()));
+        // Neighboring descriptors are untouched.
+        assert_eq!(pairs[1], ("key.two".to_string(), "other".to_string()));
+        assert!(find_descriptor_by_tag(&region, 0x42).unwrap().is_some());
+    }
+
+    #[test]
+    fn set_property_value_inplace_length_mismatch_fails() {
+        let mut region = fake_property_descriptor(b"key", b"value");
+        assert_eq!(
+            set_property_value_inplace(&mut region, "key", b"longer value").unwrap_err(),
+            DescriptorError::InvalidSize
+        );
+        // The region is left unmodified on failure.
+        assert_eq!(property_pairs(&region)[0].1, "value");
+    }
+
+    #[test]
+    fn set_property_value_inplace_missing_key_fails() {
+        let mut region = fake_property_descriptor(b"key", b"value");
+        assert_eq!(
+            set_property_value_inplace(&mut region, "other", b"value").unwrap_err(),
+            DescriptorError::InvalidContents
+        );
+    }
+
+    #[test]
+    fn find_descriptor_by_tag_unaligned_size_fails() {
+        let mut region = fake_descriptor(0x42);
+        // Corrupt `num_bytes_following` to a non-8-byte-aligned value.
+        region[15] = 0x07;
+        assert_eq!(
+            find_descriptor_by_tag(&region, 0x42).unwrap_err(),
+            DescriptorError::InvalidHeader
+        );
+    }
+}
@@ -0,0 +1,44 @@
+// Copyright 2026, The Android Open Source Project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shared fixtures for the descriptor test modules.
+
+use alloc::vec::Vec;
+
+/// Encodes a fake descriptor with the given tag and 8 bytes of body contents.
+pub(super) fn fake_descriptor(tag: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&tag.to_be_bytes());
+    bytes.extend_from_slice(&8u64.to_be_bytes()); // num_bytes_following
+    bytes.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]); // fake contents
+    bytes
+}
+
+/// Encodes a raw property descriptor with the given key and value bytes.
+pub(super) fn fake_property_descriptor(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let body_len = key.len() + 1 + value.len() + 1;
+    let num_bytes_following = 16 + body_len.next_multiple_of(8);
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&0u64.to_be_bytes()); // tag = AVB_DESCRIPTOR_TAG_PROPERTY
+    bytes.extend_from_slice(&(num_bytes_following as u64).to_be_bytes());
+    bytes.extend_from_slice(&(key.len() as u64).to_be_bytes());
+    bytes.extend_from_slice(&(value.len() as u64).to_be_bytes());
+    bytes.extend_from_slice(key);
+    bytes.push(0);
+    bytes.extend_from_slice(value);
+    bytes.push(0);
+    bytes.resize(16 + num_bytes_following, 0);
+    bytes
+}
@@ -42,6 +42,58 @@ where
     }
 }
 
+/// Verifies that the generic descriptor tag at the front of `data` matches `expected`.
+///
+/// The libavb sub-type validators don't reliably reject a descriptor of the wrong type, so
+/// the typed parsers call this first to prevent misrouted parsing from producing garbage.
+///
+/// # Arguments
+/// * `data`: the descriptor contents in raw (big-endian) format.
+/// * `expected`: the `AvbDescriptorTag` value the caller is about to parse as.
+///
+/// # Returns
+/// `Ok(())` if the tag matches, `DescriptorError::WrongType` otherwise.
+pub(super) fn check_descriptor_tag(data: &[u8], expected: u64) -> DescriptorResult<()> {
+    let (found, _) = super::region::peek_descriptor_header(data)?;
+    if found != expected {
+        return Err(DescriptorError::WrongType { expected, found });
+    }
+    Ok(())
+}
+
+/// Cross-checks a descriptor's declared variable-length fields against its body.
+///
+/// Each typed parser declares one or more length fields in its header (key, value, salt,
+/// digest, public key, cmdline, ...) and the encoder lays the corresponding byte ranges out
+/// back-to-back, followed by padding to reach 8-byte alignment. The libavb validators only
+/// check the overall descriptor framing, so a header whose lengths exceed the body would
+/// otherwise read past the declared fields. Trailing bytes beyond the declared fields are
+/// treated as padding without an upper bound: libavb accepts over-padded descriptors, and
+/// padding-only differences must not affect parsing (see `semantic_eq()` and
+/// `content_digest()` on `PropertyDescriptor`).
+///
+/// # Arguments
+/// * `body`: the descriptor body (everything after the sub-type header).
+/// * `lengths`: the declared length fields, in layout order.
+///
+/// # Returns
+/// `Ok(())` if the declared lengths fit in the body,
+/// `DescriptorError::InvalidSize` if they overshoot it, or
+/// `DescriptorError::InvalidValue` if the sum overflows.
+pub(super) fn validate_body_lengths(body: &[u8], lengths: &[u64]) -> DescriptorResult<()> {
+    let mut declared: u64 = 0;
+    for length in lengths {
+        declared = declared
+            .checked_add(*length)
+            .ok_or(DescriptorError::InvalidValue)?;
+    }
+    let declared = usize::try_from(declared).map_err(|_| DescriptorError::InvalidValue)?;
+    if declared > body.len() {
+        return Err(DescriptorError::InvalidSize);
+    }
+    Ok(())
+}
+
 /// Function type for the `avb_*descriptor_validate_and_byteswap()` C functions.
 pub(super) type ValidationFunc<T> = unsafe extern "C" fn(*const T, *mut T) -> bool;
 
@@ -84,6 +136,14 @@ pub(super) fn parse_descriptor<T>(data: &[u8]) -> DescriptorResult<ParsedDescrip
 where
     T: Default + FromBytes + Immutable + KnownLayout + ValidateAndByteswap,
 {
+    // Cross-check the generic length prefix before trusting the sub-type header: the data
+    // must actually contain the `num_bytes_following` bytes the header claims, otherwise a
+    // malformed header would only be caught later (and less clearly) by a body split.
+    let (_, total_size) = super::region::peek_descriptor_header(data)?;
+    if data.len() < total_size {
+        return Err(DescriptorError::InvalidSize);
+    }
+
     let (raw_header, body) =
         Ref::<_, T>::from_prefix(data).map_err(|_| DescriptorError::InvalidHeader)?;
     let raw_header = Ref::into_ref(raw_header);
@@ -131,6 +191,39 @@ mod tests {
         assert_eq!(split_slice(data, 5u32), Err(DescriptorError::InvalidSize));
     }
 
+    #[test]
+    fn validate_body_lengths_exact_fit_succeeds() {
+        assert_eq!(validate_body_lengths(&[0; 16], &[10, 6]), Ok(()));
+    }
+
+    #[test]
+    fn validate_body_lengths_with_alignment_padding_succeeds() {
+        assert_eq!(validate_body_lengths(&[0; 16], &[5, 4]), Ok(()));
+    }
+
+    #[test]
+    fn validate_body_lengths_overshooting_body_fails() {
+        assert_eq!(
+            validate_body_lengths(&[0; 16], &[10, 10]),
+            Err(DescriptorError::InvalidSize)
+        );
+    }
+
+    #[test]
+    fn validate_body_lengths_with_surplus_padding_succeeds() {
+        // A full alignment unit (or more) of trailing padding is still padding;
+        // libavb tolerates over-padded descriptors and so do we.
+        assert_eq!(validate_body_lengths(&[0; 16], &[4, 4]), Ok(()));
+    }
+
+    #[test]
+    fn validate_body_lengths_sum_overflow_fails() {
+        assert_eq!(
+            validate_body_lengths(&[0; 16], &[u64::MAX, 1]),
+            Err(DescriptorError::InvalidValue)
+        );
+    }
+
     // Enable `parse_descriptor()` on a generic `AvbDescriptor` of any sub-type.
     // SAFETY: `VALIDATE_AND_BYTESWAP_FUNC` is the correct libavb validator for this descriptor.
     unsafe impl ValidateAndByteswap for AvbDescriptor {
@@ -166,6 +259,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_descriptor_length_prefix_exceeds_body_failure() {
+        let mut descriptor = TEST_DESCRIPTOR.to_vec();
+        // Claim 16 bytes following the header while only 8 are present.
+        descriptor[15] = 0x10;
+        assert_eq!(
+            parse_descriptor::<AvbDescriptor>(&descriptor).unwrap_err(),
+            DescriptorError::InvalidSize
+        );
+    }
+
     #[test]
     fn parse_descriptor_wrong_type_failure() {
         assert_eq!(
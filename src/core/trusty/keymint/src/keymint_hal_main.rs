@@ -79,8 +79,17 @@ struct Args {
     dev: String,
 }
 
+/// System property that opts into exiting with `FAILURE_EXIT_CODE` instead of panicking when
+/// the service fails, so init's restart/backoff policy applies cleanly.
+const CLEAN_EXIT_PROPERTY: &str = "keymint.hal.clean_exit";
+const FAILURE_EXIT_CODE: i32 = 2;
+
 fn main() {
     if let Err(HalServiceError(e)) = inner_main() {
+        if rustutils::system_properties::read_bool(CLEAN_EXIT_PROPERTY, false).unwrap_or(false) {
+            error!("HAL service failed, exiting with code {FAILURE_EXIT_CODE}: {e:?}");
+            std::process::exit(FAILURE_EXIT_CODE);
+        }
         panic!("HAL service failed: {e:?}");
     }
 }
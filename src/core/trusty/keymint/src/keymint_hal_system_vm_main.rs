@@ -18,44 +18,1002 @@
 
 use android_trusty_commservice::aidl::android::trusty::commservice::ICommService::ICommService;
 use anyhow::{anyhow, bail, Context, Result};
-use binder::{self, AccessorProvider, ProcessState, Strong};
+use binder::{self, AccessorProvider, IBinder, ProcessState, Strong};
+use clap::Parser;
 use kmr_hal::{register_binder_services, send_hal_info, SerializedChannel, ALL_HALS};
 use log::{error, info, warn};
 use std::{
     ops::DerefMut,
     panic,
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 const SERVICE_INSTANCE: &str = "default";
 
+/// How many times to attempt creating the accessor provider before giving up. The accessor
+/// service may not be registered yet during early boot.
+const ACCESSOR_PROVIDER_ATTEMPTS: u32 = 5;
+const ACCESSOR_PROVIDER_RETRY_DELAY: Duration = Duration::from_millis(500);
+
 const ACCESSOR_SERVICE_NAME: &str = "android.os.IAccessor/ICommService/security_vm_keymint";
 const INTERNAL_RPC_SERVICE_NAME: &str =
     "android.trusty.commservice.ICommService/security_vm_keymint";
 
+/// Build identification reported in diagnostics so field bugs can be correlated with the
+/// exact binary that produced them.
 #[derive(Debug)]
+struct BuildInfo {
+    /// Build id stamped at compile time, or "unknown" for local builds.
+    build_id: &'static str,
+    /// Whether the `nonsecure` feature was compiled in.
+    nonsecure: bool,
+    /// The negotiated channel message size limit.
+    max_size: usize,
+    /// The binder service instance the HALs register under.
+    service_instance: &'static str,
+}
+
+const BUILD_INFO: BuildInfo = BuildInfo {
+    build_id: match option_env!("BUILD_NUMBER") {
+        Some(id) => id,
+        None => "unknown",
+    },
+    nonsecure: cfg!(feature = "nonsecure"),
+    max_size: CommServiceChannel::MAX_SIZE,
+    service_instance: SERVICE_INSTANCE,
+};
+
+impl std::fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "build_id={} nonsecure={} max_size={} service_instance={}",
+            self.build_id, self.nonsecure, self.max_size, self.service_instance
+        )
+    }
+}
+
+/// Upper bounds in milliseconds for the transaction latency histogram buckets.
+const LATENCY_BUCKETS_MS: [u64; 5] = [1, 5, 10, 50, 100];
+
+/// Upper bounds in bytes for the request/response size histogram buckets: powers of two up
+/// to the channel's `MAX_SIZE`, plus an implicit overflow bucket. A populated overflow
+/// bucket means payloads exceeded `MAX_SIZE` and chunking/size negotiation was exercised.
+const SIZE_BUCKETS_BYTES: [usize; 7] = [64, 128, 256, 512, 1024, 2048, 4096];
+
+/// Maps a payload length to its size histogram bucket index.
+fn size_bucket(len: usize) -> usize {
+    SIZE_BUCKETS_BYTES
+        .iter()
+        .position(|&bound| len <= bound)
+        .unwrap_or(SIZE_BUCKETS_BYTES.len())
+}
+
+/// Counters describing channel usage. Updated on every transaction and shared with the
+/// optional metrics exporter.
+#[derive(Debug, Default)]
+struct ChannelStats {
+    transactions: AtomicU64,
+    errors: AtomicU64,
+    request_bytes: AtomicU64,
+    response_bytes: AtomicU64,
+    /// Latency counts per bucket, with a final overflow bucket for slow transactions.
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS_MS.len() + 1],
+    /// Request size counts per bucket, with a final overflow bucket for chunked payloads.
+    request_size_buckets: [AtomicU64; SIZE_BUCKETS_BYTES.len() + 1],
+    /// Response size counts per bucket, with a final overflow bucket for chunked payloads.
+    response_size_buckets: [AtomicU64; SIZE_BUCKETS_BYTES.len() + 1],
+}
+
+impl ChannelStats {
+    /// Records one completed transaction.
+    fn record(&self, request_len: usize, result: &binder::Result<Vec<u8>>, elapsed: Duration) {
+        self.transactions.fetch_add(1, Ordering::Relaxed);
+        self.request_bytes.fetch_add(request_len as u64, Ordering::Relaxed);
+        self.request_size_buckets[size_bucket(request_len)].fetch_add(1, Ordering::Relaxed);
+        match result {
+            Ok(response) => {
+                self.response_bytes.fetch_add(response.len() as u64, Ordering::Relaxed);
+                self.response_size_buckets[size_bucket(response.len())]
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let elapsed_ms = elapsed.as_millis() as u64;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| elapsed_ms <= bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Renders the counters in the prometheus text exposition format.
+    #[cfg(feature = "metrics")]
+    fn render(&self) -> String {
+        use std::fmt::Write;
+
+        let counters = [
+            ("keymint_hal_transactions_total", &self.transactions),
+            ("keymint_hal_errors_total", &self.errors),
+            ("keymint_hal_request_bytes_total", &self.request_bytes),
+            ("keymint_hal_response_bytes_total", &self.response_bytes),
+        ];
+        let mut out = String::new();
+        for (name, value) in counters {
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+        }
+
+        let _ = writeln!(out, "# TYPE keymint_hal_transaction_latency_ms histogram");
+        let mut cumulative = 0;
+        for (bucket, bound) in self.latency_buckets.iter().zip(
+            LATENCY_BUCKETS_MS
+                .iter()
+                .map(|b| b.to_string())
+                .chain(std::iter::once("+Inf".to_string())),
+        ) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "keymint_hal_transaction_latency_ms_bucket{{le=\"{bound}\"}} {cumulative}"
+            );
+        }
+
+        let size_histograms = [
+            ("keymint_hal_request_size_bytes", &self.request_size_buckets),
+            ("keymint_hal_response_size_bytes", &self.response_size_buckets),
+        ];
+        for (name, buckets) in size_histograms {
+            let _ = writeln!(out, "# TYPE {name} histogram");
+            let mut cumulative = 0;
+            for (bucket, bound) in buckets.iter().zip(
+                SIZE_BUCKETS_BYTES
+                    .iter()
+                    .map(|b| b.to_string())
+                    .chain(std::iter::once("+Inf".to_string())),
+            ) {
+                cumulative += bucket.load(Ordering::Relaxed);
+                let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+            }
+        }
+
+        let gauges = [
+            ("keymint_hal_queue_depth", &QUEUE_DEPTH),
+            ("keymint_hal_queue_high_water", &QUEUE_HIGH_WATER),
+        ];
+        for (name, value) in gauges {
+            let _ = writeln!(out, "# TYPE {name} gauge");
+            let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+        }
+        out
+    }
+}
+
+/// Consecutive transaction failures on one endpoint before failing over to the next.
+const FAILOVER_THRESHOLD: u32 = 3;
+
+/// System property naming a backup commservice instance to fail over to. Unset means no
+/// failover.
+const BACKUP_RPC_SERVICE_PROPERTY: &str = "keymint.hal.backup_rpc_service";
+
+// No `Debug` derive: `binder::DeathRecipient` doesn't implement it.
 struct CommServiceChannel {
-    comm_service: Strong<dyn ICommService>,
+    /// The connection to the VM. `None` after an idle disconnect or failover; lazily
+    /// re-acquired by the next transaction.
+    comm_service: Option<Strong<dyn ICommService>>,
+    /// Set by the death recipient when the commservice binder dies, so the next
+    /// transaction drops the stale connection instead of failing on a dead binder.
+    vm_died: Arc<AtomicBool>,
+    /// Keeps the linked death recipient alive for the lifetime of the connection.
+    death_recipient: Option<binder::DeathRecipient>,
+    stats: Arc<ChannelStats>,
+    /// When the channel last completed a transaction, for idle-disconnect tracking.
+    last_used: Instant,
+    /// Ordered commservice endpoints; the first entry is the primary instance.
+    endpoints: Vec<String>,
+    /// Index into `endpoints` of the instance currently in use.
+    active_endpoint: usize,
+    /// Consecutive transaction failures on the active endpoint.
+    consecutive_failures: u32,
+    /// Whether the TA has received the HAL info since the last (re)connection.
+    hal_info_sent: bool,
+}
+
+impl CommServiceChannel {
+    /// Acquires an `ICommService` connection to the named instance.
+    fn connect_endpoint(name: &str) -> Result<Strong<dyn ICommService>, binder::StatusCode> {
+        binder::wait_for_interface(name)
+    }
+
+    /// Switches to the next configured endpoint after persistent failures. Transactions are
+    /// stateless, so switching between calls is safe.
+    fn fail_over(&mut self) {
+        let previous = self.active_endpoint;
+        self.active_endpoint = (self.active_endpoint + 1) % self.endpoints.len();
+        warn!(
+            "Failing over from {} to {} after {} consecutive errors.",
+            self.endpoints[previous], self.endpoints[self.active_endpoint], self.consecutive_failures
+        );
+        self.comm_service = None;
+        self.death_recipient = None;
+        self.consecutive_failures = 0;
+    }
+
+    /// Links a death recipient to the current connection's binder.
+    ///
+    /// A VM crash then gets logged the moment it happens and the channel is marked
+    /// disconnected, rather than the death only being discovered as a failed transaction
+    /// later. Linking is best-effort; an unlinked channel still recovers via the error
+    /// path in `execute`.
+    fn link_death_recipient(&mut self) {
+        let Some(comm_service) = &self.comm_service else {
+            return;
+        };
+        let vm_died = self.vm_died.clone();
+        let mut recipient = binder::DeathRecipient::new(move || {
+            error!("Commservice binder died; channel marked disconnected.");
+            vm_died.store(true, Ordering::Relaxed);
+        });
+        match comm_service.as_binder().link_to_death(&mut recipient) {
+            Ok(()) => self.death_recipient = Some(recipient),
+            Err(e) => warn!("Failed to link death recipient: {e:?}"),
+        }
+    }
+
+    /// Sends the HAL service information to the TA, recording the delivery so it is only
+    /// re-sent after a reconnection.
+    fn send_hal_info(&mut self) -> Result<()> {
+        // Mark delivery first: `send_hal_info` drives `execute` on this same channel and
+        // would otherwise recurse.
+        self.hal_info_sent = true;
+        send_hal_info(self).map_err(|e| anyhow!("failed to populate HAL info: {e:?}"))?;
+        #[cfg(feature = "hal-info-ack")]
+        self.verify_hal_info_ack()?;
+        Ok(())
+    }
+
+    /// Queries the TA's acknowledgment of the HAL info it just received.
+    ///
+    /// A TA that supports the query answers `ACK` if it accepted the info or `NAK:<reason>`
+    /// if it rejected it; an explicit rejection fails startup so a HAL/TA mismatch surfaces
+    /// at boot rather than during the first key operation. Anything else (including a TA
+    /// that doesn't implement the query) is logged and tolerated.
+    #[cfg(feature = "hal-info-ack")]
+    fn verify_hal_info_ack(&mut self) -> Result<()> {
+        /// Reserved request querying the TA's verdict on the last HAL info delivery.
+        const HAL_INFO_ACK_REQUEST: &[u8] = b"\0HALI";
+
+        match self.execute(HAL_INFO_ACK_REQUEST) {
+            Ok(response) if response == b"ACK" => {
+                info!("TA acknowledged the HAL info.");
+                Ok(())
+            }
+            Ok(response) if response.starts_with(b"NAK") => {
+                let reason = String::from_utf8_lossy(&response).into_owned();
+                Err(anyhow!("TA rejected the HAL info: {reason}"))
+            }
+            Ok(response) => {
+                info!(
+                    "TA gave no verdict on the HAL info ({}-byte response); continuing.",
+                    response.len()
+                );
+                Ok(())
+            }
+            Err(e) => {
+                info!("TA does not support HAL info acknowledgment ({e:?}); continuing.");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// System property that opts into treating zero-length responses as valid. A serialized
+/// KeyMint response is never legitimately empty, so an empty `Vec` from the VM almost
+/// always means a transport bug; the property exists as an escape hatch for VMs that
+/// genuinely answer some operation with no payload.
+const ALLOW_EMPTY_RESPONSES_PROPERTY: &str = "keymint.hal.allow_empty_responses";
+
+/// Frame marker prefixed to each chunk of an oversized request. Chunk-aware commservice
+/// implementations strip the marker and reassemble; the marker starts with a NUL byte so it
+/// cannot collide with a legitimate CBOR-serialized KeyMint request.
+const CHUNK_MARKER: &[u8] = b"\0CHNK";
+
+/// Size of the per-chunk frame header: marker plus big-endian chunk index and chunk count.
+const CHUNK_HEADER_SIZE: usize = CHUNK_MARKER.len() + 2 * std::mem::size_of::<u32>();
+
+/// System property forcing a smaller effective channel size, overriding `MAX_SIZE`. This
+/// exists so QA can exercise the chunking/reassembly path on normal hardware without a
+/// genuinely constrained VM; it can only shrink the limit, never grow it.
+const MAX_SIZE_OVERRIDE_PROPERTY: &str = "keymint.hal.max_size_override";
+
+/// Smallest accepted `MAX_SIZE` override: enough for a chunk header plus some payload.
+const MIN_MAX_SIZE_OVERRIDE: usize = 64;
+
+/// The channel size limit in effect: the validated override if configured, `MAX_SIZE`
+/// otherwise. Read once since the property cannot meaningfully change mid-run.
+fn effective_max_size() -> usize {
+    static SIZE: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+    *SIZE.get_or_init(|| {
+        let default = CommServiceChannel::MAX_SIZE;
+        let Ok(Some(value)) = rustutils::system_properties::read(MAX_SIZE_OVERRIDE_PROPERTY)
+        else {
+            return default;
+        };
+        match value.parse::<usize>() {
+            Ok(size) if (MIN_MAX_SIZE_OVERRIDE..=default).contains(&size) => {
+                warn!("Channel size limit overridden to {size} bytes for testing.");
+                size
+            }
+            _ => {
+                error!(
+                    "Ignoring {MAX_SIZE_OVERRIDE_PROPERTY}='{value}'; must be an integer in \
+                     [{MIN_MAX_SIZE_OVERRIDE}, {default}]. Using {default}."
+                );
+                default
+            }
+        }
+    })
+}
+
+impl CommServiceChannel {
+    /// Sends a request larger than `MAX_SIZE` as a sequence of marker-framed chunks.
+    ///
+    /// Each frame carries the marker, the chunk index, the total chunk count, and a payload
+    /// slice, so every frame fits within `MAX_SIZE`. The VM buffers the payloads and only
+    /// executes the reassembled request when the final frame arrives; the final frame's
+    /// response is the response to the whole request, earlier frames just acknowledge.
+    ///
+    /// This is what keeps `send_hal_info` working if the HAL info payload ever outgrows the
+    /// channel limit: the info send runs through `execute` like any other transaction.
+    fn execute_chunked(
+        comm_service: &Strong<dyn ICommService>,
+        serialized_req: &[u8],
+    ) -> binder::Result<Vec<u8>> {
+        let payload_size = effective_max_size() - CHUNK_HEADER_SIZE;
+        let chunks: Vec<&[u8]> = serialized_req.chunks(payload_size).collect();
+        let total = chunks.len() as u32;
+        info!(
+            "Splitting {}-byte request into {} chunks of at most {} bytes.",
+            serialized_req.len(),
+            total,
+            payload_size
+        );
+        let mut response = Vec::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            let mut frame = Vec::with_capacity(CHUNK_HEADER_SIZE + chunk.len());
+            frame.extend_from_slice(CHUNK_MARKER);
+            frame.extend_from_slice(&(index as u32).to_be_bytes());
+            frame.extend_from_slice(&total.to_be_bytes());
+            frame.extend_from_slice(chunk);
+            response = comm_service.execute_transact(&frame)?;
+        }
+        Ok(response)
+    }
+}
+
+/// RAII guard for an ATrace span covering one commservice transaction, so KeyMint stalls
+/// line up with the rest of the system in a Perfetto capture. The guard ends the span on
+/// drop, which keeps early error returns from leaving a span open. Compiled out entirely
+/// without the `atrace` feature, so the transaction path pays nothing when it's off.
+#[cfg(feature = "atrace")]
+struct AtraceSpan;
+
+#[cfg(feature = "atrace")]
+impl AtraceSpan {
+    fn begin(name: &str) -> Self {
+        atrace::atrace_begin(atrace::AtraceTag::Hal, name);
+        AtraceSpan
+    }
+}
+
+#[cfg(feature = "atrace")]
+impl Drop for AtraceSpan {
+    fn drop(&mut self) {
+        atrace::atrace_end(atrace::AtraceTag::Hal);
+    }
 }
 
 impl SerializedChannel for CommServiceChannel {
     const MAX_SIZE: usize = 4000;
     fn execute(&mut self, serialized_req: &[u8]) -> binder::Result<Vec<u8>> {
-        self.comm_service.execute_transact(serialized_req)
+        if self.vm_died.swap(false, Ordering::Relaxed) {
+            warn!("Commservice binder died since the last transaction; reconnecting.");
+            self.comm_service = None;
+            self.death_recipient = None;
+        }
+        if self.comm_service.is_none() {
+            info!(
+                "Acquiring ICommService connection to {}.",
+                self.endpoints[self.active_endpoint]
+            );
+            self.comm_service = Some(Self::connect_endpoint(
+                &self.endpoints[self.active_endpoint],
+            )?);
+            // The TA behind a fresh connection may have lost the HAL info.
+            self.hal_info_sent = false;
+            self.link_death_recipient();
+        }
+        if !self.hal_info_sent {
+            info!("Re-sending HAL info over fresh connection.");
+            if let Err(e) = self.send_hal_info() {
+                warn!("Failed to re-send HAL info: {e:?}");
+                self.hal_info_sent = false;
+            }
+        }
+        // We can always unwrap here because we just ensured the connection exists.
+        let comm_service = self.comm_service.as_ref().unwrap();
+        #[cfg(feature = "atrace")]
+        let _span = AtraceSpan::begin(&format!(
+            "keymint_execute {} bytes{}",
+            serialized_req.len(),
+            if serialized_req.len() > effective_max_size() { " (chunked)" } else { "" }
+        ));
+        let start = Instant::now();
+        let result = if serialized_req.len() > effective_max_size() {
+            Self::execute_chunked(comm_service, serialized_req)
+        } else {
+            comm_service.execute_transact(serialized_req)
+        };
+        // A zero-length "success" would be misinterpreted by KeyMint deserialization; turn
+        // it into an explicit error unless a VM has opted into empty responses.
+        let result = match result {
+            Ok(response)
+                if response.is_empty()
+                    && !rustutils::system_properties::read_bool(
+                        ALLOW_EMPTY_RESPONSES_PROPERTY,
+                        false,
+                    )
+                    .unwrap_or(false) =>
+            {
+                error!(
+                    "Received zero-length response to a {}-byte request; rejecting.",
+                    serialized_req.len()
+                );
+                Err(binder::StatusCode::BAD_VALUE.into())
+            }
+            Err(e)
+                if e == binder::StatusCode::FAILED_TRANSACTION
+                    || e == binder::StatusCode::BAD_VALUE =>
+            {
+                // These codes are what binder reports when the reply outgrew its buffer;
+                // surface the likely cause instead of a bare status code.
+                error!(
+                    "Transaction failed with {e:?}, which usually means the reply exceeded \
+                     the binder reply buffer (negotiated ceiling {REQUESTED_REPLY_SIZE} \
+                     bytes). The request was {} bytes.",
+                    serialized_req.len()
+                );
+                Err(e)
+            }
+            other => other,
+        };
+        self.stats.record(serialized_req.len(), &result, start.elapsed());
+        #[cfg(feature = "channel-trace")]
+        capture_transaction(serialized_req, &result);
+        self.last_used = Instant::now();
+        match &result {
+            Ok(_) => self.consecutive_failures = 0,
+            Err(_) => {
+                self.consecutive_failures += 1;
+                if self.consecutive_failures >= FAILOVER_THRESHOLD && self.endpoints.len() > 1 {
+                    self.fail_over();
+                }
+            }
+        }
+        result
+    }
+}
+
+/// System property naming the file that channel transactions are appended to when the
+/// `channel-trace` feature is compiled in. Unset disables capture.
+#[cfg(feature = "channel-trace")]
+const CHANNEL_TRACE_PATH_PROPERTY: &str = "keymint.hal.channel_trace_path";
+
+/// Appends one transaction to the channel trace file, if capture is configured.
+///
+/// The trace is a flat sequence of frames, each `u32` big-endian request length, request
+/// bytes, `u32` big-endian response length, response bytes. Failed transactions are not
+/// captured since they carry no response to replay.
+#[cfg(feature = "channel-trace")]
+fn capture_transaction(serialized_req: &[u8], result: &binder::Result<Vec<u8>>) {
+    use std::io::Write;
+    use std::sync::OnceLock;
+
+    static TRACE_PATH: OnceLock<Option<String>> = OnceLock::new();
+    let Some(path) = TRACE_PATH
+        .get_or_init(|| rustutils::system_properties::read(CHANNEL_TRACE_PATH_PROPERTY).ok().flatten())
+    else {
+        return;
+    };
+    let Ok(response) = result else {
+        return;
+    };
+    let mut frame = Vec::with_capacity(8 + serialized_req.len() + response.len());
+    frame.extend_from_slice(&(serialized_req.len() as u32).to_be_bytes());
+    frame.extend_from_slice(serialized_req);
+    frame.extend_from_slice(&(response.len() as u32).to_be_bytes());
+    frame.extend_from_slice(response);
+    let write = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(&frame));
+    if let Err(e) = write {
+        warn!("Failed to append channel trace frame to {path}: {e:?}");
+    }
+}
+
+/// A fake channel that replays a captured transaction log.
+///
+/// Requests must arrive in the captured order with identical bytes; each one is answered
+/// with the recorded response. Any divergence is an error rather than a silent wrong
+/// answer, so a replay that passes really did reproduce the captured session. This makes
+/// field bug reports reproducible offline without a Trusty VM.
+#[cfg(feature = "channel-trace")]
+struct ReplayChannel {
+    /// Remaining `(request, response)` frames, in capture order.
+    frames: std::collections::VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+#[cfg(feature = "channel-trace")]
+impl ReplayChannel {
+    /// Loads a capture produced by `capture_transaction()`.
+    fn load(path: &std::path::Path) -> Result<Self> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("failed to read channel trace {}", path.display()))?;
+        let mut frames = std::collections::VecDeque::new();
+        let mut remaining = &data[..];
+        let mut read_chunk = |remaining: &mut &[u8]| -> Result<Vec<u8>> {
+            let (len, rest) = remaining
+                .split_at_checked(4)
+                .context("truncated channel trace length")?;
+            let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+            let (chunk, rest) = rest
+                .split_at_checked(len)
+                .context("truncated channel trace payload")?;
+            *remaining = rest;
+            Ok(chunk.to_vec())
+        };
+        while !remaining.is_empty() {
+            let request = read_chunk(&mut remaining)?;
+            let response = read_chunk(&mut remaining)?;
+            frames.push_back((request, response));
+        }
+        info!("Loaded {} channel trace frames from {}.", frames.len(), path.display());
+        Ok(Self { frames })
+    }
+}
+
+#[cfg(feature = "channel-trace")]
+impl SerializedChannel for ReplayChannel {
+    const MAX_SIZE: usize = CommServiceChannel::MAX_SIZE;
+    fn execute(&mut self, serialized_req: &[u8]) -> binder::Result<Vec<u8>> {
+        match self.frames.pop_front() {
+            Some((request, response)) if request == serialized_req => Ok(response),
+            Some((request, _)) => {
+                error!(
+                    "Replay diverged: expected a {}-byte captured request, got {} bytes.",
+                    request.len(),
+                    serialized_req.len()
+                );
+                Err(binder::StatusCode::BAD_VALUE.into())
+            }
+            None => {
+                error!("Replay exhausted: no captured frames remain.");
+                Err(binder::StatusCode::BAD_VALUE.into())
+            }
+        }
+    }
+}
+
+/// Returns the binder service names that `register_binder_services()` registers for the
+/// given instance.
+fn registered_service_names(instance: &str) -> Vec<String> {
+    ALL_HALS
+        .iter()
+        .map(|hal| format!("{hal}/{instance}"))
+        .collect()
+}
+
+/// Logs whether each registered service is currently retrievable from servicemanager.
+///
+/// `register_binder_services()` returning `Ok` doesn't prove every interface stayed
+/// registered; this read-only pass diagnoses partial registration where some KeyMint
+/// interfaces are missing.
+fn report_service_status(instance: &str) {
+    for name in registered_service_names(instance) {
+        if binder::check_service(&name).is_some() {
+            info!("Service {name} is registered and retrievable.");
+        } else {
+            error!("Service {name} is NOT retrievable from servicemanager.");
+        }
+    }
+}
+
+/// Reply-buffer size requested from the commservice at startup, and the assumed ceiling
+/// when negotiation is unsupported. Large attestation replies can overflow the default
+/// binder reply buffer even when they are within `MAX_SIZE`.
+const REQUESTED_REPLY_SIZE: u32 = 64 * 1024;
+
+/// Reserved request asking the commservice to raise its reply buffer to the appended
+/// big-endian `u32` size. Older VMs reject it, which the negotiation tolerates.
+const REPLY_SIZE_NEGOTIATION_REQUEST: &[u8] = b"\0MAXR";
+
+/// Asks the commservice to grow its reply buffer, logging the resulting ceiling.
+///
+/// Purely best-effort: a VM that doesn't implement the negotiation keeps its default
+/// buffer, and oversized replies will surface through the overflow diagnostics in
+/// `execute`.
+fn negotiate_reply_size(channel: &HalChannel) {
+    let mut request = Vec::from(REPLY_SIZE_NEGOTIATION_REQUEST);
+    request.extend_from_slice(&REQUESTED_REPLY_SIZE.to_be_bytes());
+    match channel.with(|c| {
+        c.execute(&request)
+            .map_err(|e| anyhow!("negotiation rejected: {e:?}"))
+    }) {
+        Ok(_) => info!("Commservice reply buffer raised to {REQUESTED_REPLY_SIZE} bytes."),
+        Err(e) => info!(
+            "Commservice keeps its default reply buffer; oversized replies will fail \
+             ({e:?})."
+        ),
+    }
+}
+
+/// System property holding the identity token the VM is expected to present. Unset
+/// disables identity verification.
+const EXPECTED_VM_IDENTITY_PROPERTY: &str = "keymint.hal.expected_vm_identity";
+
+/// Reserved request asking the commservice for its identity token.
+const IDENTITY_REQUEST: &[u8] = b"\0IDNT";
+
+/// Verifies the VM's identity token against the configured expectation.
+///
+/// Defense in depth for the channel: an impostor bound to the same service name would
+/// otherwise receive the HAL info and serve key operations. When the expectation property
+/// is set, the VM must present a matching token before anything is sent or published; a
+/// mismatch, or a VM that cannot answer the handshake at all, refuses startup. When the
+/// property is unset, behavior is unchanged.
+fn verify_channel_identity(channel: &HalChannel) -> Result<()> {
+    let Ok(Some(expected)) = rustutils::system_properties::read(EXPECTED_VM_IDENTITY_PROPERTY)
+    else {
+        return Ok(());
+    };
+    let response = channel
+        .with(|c| {
+            c.execute(IDENTITY_REQUEST)
+                .map_err(|e| anyhow!("identity handshake rejected: {e:?}"))
+        })
+        .context("VM did not answer the identity handshake; refusing to start")?;
+    let presented = String::from_utf8_lossy(&response);
+    if presented != expected {
+        error!(
+            "VM identity mismatch: expected configured token, got a {}-byte token that does \
+             not match. Refusing to start.",
+            response.len()
+        );
+        bail!("commservice identity verification failed");
+    }
+    info!("VM identity verified against {EXPECTED_VM_IDENTITY_PROPERTY}.");
+    Ok(())
+}
+
+/// Reserved request payload understood by newer commservice implementations as a
+/// capabilities query. Older VMs reject it, which the probe tolerates.
+const CAPABILITIES_PROBE_REQUEST: &[u8] = b"\0CAPS";
+
+/// Runs a single diagnostic transaction against the VM and reports pass/fail, for use as a
+/// CI smoke test without spinning up the full service.
+fn run_check(channel: &HalChannel, instance: &str) -> Result<()> {
+    // Report which of the expected services are visible (e.g. from an already-running
+    // instance) before exercising the channel.
+    for name in registered_service_names(instance) {
+        let status = if binder::check_service(&name).is_some() {
+            "registered"
+        } else {
+            "not registered"
+        };
+        println!("service: {name} ({status})");
+    }
+    match channel.with(|c| {
+        c.execute(CAPABILITIES_PROBE_REQUEST)
+            .map_err(|e| anyhow!("diagnostic transaction failed: {e:?}"))
+    }) {
+        Ok(_) => {
+            println!("check: PASS");
+            Ok(())
+        }
+        Err(e) => {
+            println!("check: FAIL ({e:?})");
+            Err(e)
+        }
+    }
+}
+
+/// Channel test double that answers every request with a fixed non-empty payload: enough
+/// for service construction, registration and the HAL info send to complete without a VM
+/// behind the channel.
+struct StubChannel;
+
+impl SerializedChannel for StubChannel {
+    const MAX_SIZE: usize = CommServiceChannel::MAX_SIZE;
+    fn execute(&mut self, _serialized_req: &[u8]) -> binder::Result<Vec<u8>> {
+        // An empty CBOR array, the smallest response the deserializers accept as a reply.
+        Ok(vec![0x80])
+    }
+}
+
+/// Reusable channel test double for exercising the `kmr_hal` wiring without a VM.
+///
+/// Unlike `StubChannel`, which is the minimal always-succeeds double behind `--once`, this
+/// one is built for assertions: responses are configurable, errors injectable, and every
+/// request is recorded in call order. Compiled only under the `test-util` feature so
+/// downstream integrators can drive `register_binder_services()` / `send_hal_info()`
+/// against it.
+#[cfg(feature = "test-util")]
+pub struct FakeChannel {
+    /// Responses returned in queue order; once exhausted, every request gets
+    /// `default_response`.
+    pub responses: std::collections::VecDeque<binder::Result<Vec<u8>>>,
+    /// Response used once `responses` is exhausted.
+    pub default_response: binder::Result<Vec<u8>>,
+    /// Every request passed to `execute()`, in call order.
+    pub requests: Vec<Vec<u8>>,
+}
+
+#[cfg(feature = "test-util")]
+impl Default for FakeChannel {
+    fn default() -> Self {
+        Self {
+            responses: std::collections::VecDeque::new(),
+            // An empty CBOR array, the smallest response the deserializers accept.
+            default_response: Ok(vec![0x80]),
+            requests: Vec::new(),
+        }
+    }
+}
+
+// Convenience constructors are consumed by test code, not by the binary itself.
+#[cfg(feature = "test-util")]
+#[allow(dead_code)]
+impl FakeChannel {
+    /// Queues a response for the next unanswered request.
+    pub fn push_response(&mut self, response: Vec<u8>) {
+        self.responses.push_back(Ok(response));
+    }
+
+    /// Queues an injected error for the next unanswered request.
+    pub fn push_error(&mut self, error: binder::StatusCode) {
+        self.responses.push_back(Err(error));
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl SerializedChannel for FakeChannel {
+    const MAX_SIZE: usize = CommServiceChannel::MAX_SIZE;
+    fn execute(&mut self, serialized_req: &[u8]) -> binder::Result<Vec<u8>> {
+        self.requests.push(serialized_req.to_vec());
+        self.responses.pop_front().unwrap_or_else(|| self.default_response.clone())
+    }
+}
+
+/// Runs the `--once` AIDL wiring self-test.
+///
+/// Constructs and registers every HAL service object over a stub channel, verifies each is
+/// retrievable from servicemanager, and pushes the HAL info through the stub. Passing
+/// proves the AIDL classes construct and the registration wiring holds; it deliberately
+/// proves nothing about a real VM (that's what `--check` is for).
+fn run_once(instance: &str) -> Result<()> {
+    let channel = Arc::new(Mutex::new(StubChannel));
+    register_binder_services(&channel, ALL_HALS, instance)
+        .context("failed to construct/register HAL services against the stub channel")?;
+    let missing: Vec<String> = registered_service_names(instance)
+        .into_iter()
+        .filter(|name| binder::check_service(name).is_none())
+        .collect();
+    if !missing.is_empty() {
+        println!("once: FAIL (services not retrievable: {missing:?})");
+        bail!("services not retrievable after registration: {missing:?}");
+    }
+    send_hal_info(channel.lock().unwrap().deref_mut())
+        .map_err(|e| anyhow!("failed to send HAL info over the stub channel: {e:?}"))?;
+    println!("once: PASS ({} services registered)", ALL_HALS.len());
+    Ok(())
+}
+
+/// Queries and logs the VM's reported version/capabilities.
+///
+/// Purely diagnostic: a VM that doesn't implement the query, or returns something
+/// undecodable, is logged as unknown. Run this on its own thread so a slow VM can never
+/// block startup.
+fn probe_capabilities(channel: &HalChannel) {
+    let response = channel.with(|c| {
+        c.execute(CAPABILITIES_PROBE_REQUEST)
+            .map_err(|e| anyhow!("probe rejected: {e:?}"))
+    });
+    match response {
+        Ok(response) => match std::str::from_utf8(&response) {
+            Ok(capabilities) if !capabilities.is_empty() => {
+                info!("VM reported capabilities: {capabilities}");
+            }
+            _ => info!(
+                "VM capabilities unknown (undecodable {}-byte response).",
+                response.len()
+            ),
+        },
+        Err(e) => info!("VM capabilities unknown ({e:?})."),
     }
 }
 
+/// System property holding the idle-disconnect timeout in seconds. Unset or zero disables
+/// idle disconnection.
+const IDLE_TIMEOUT_PROPERTY: &str = "keymint.hal.idle_timeout_seconds";
+
+/// Reads the configured idle-disconnect timeout, if any.
+fn idle_timeout() -> Option<Duration> {
+    let seconds: u64 = rustutils::system_properties::read(IDLE_TIMEOUT_PROPERTY)
+        .ok()
+        .flatten()?
+        .parse()
+        .ok()?;
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+/// Drops the VM connection once the channel has been idle for `timeout`, releasing the VM
+/// channel until the next transaction re-acquires it.
+fn start_idle_disconnect(channel: Arc<Mutex<CommServiceChannel>>, timeout: Duration) {
+    let channel = HalChannel(channel);
+    thread::spawn(move || loop {
+        thread::sleep(timeout / 2);
+        // A channel busy with a transaction is not idle; skip the check rather than
+        // blocking the reaper behind it.
+        let result = channel.try_with(|c| {
+            if c.comm_service.is_some() && c.last_used.elapsed() >= timeout {
+                info!("Dropping ICommService connection after {timeout:?} idle.");
+                c.comm_service = None;
+                c.death_recipient = None;
+            }
+            Ok(())
+        });
+        if result.is_err() {
+            return;
+        }
+    });
+}
+
+/// Set by the SIGUSR1 handler; the diagnostics worker logs the dump and clears it.
+static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// How often the diagnostics worker checks whether SIGUSR1 requested a dump.
+const DUMP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// SIGUSR1 handler. Only performs an atomic store: logging allocates and takes locks,
+/// neither of which is async-signal-safe, so the actual dump happens on the diagnostics
+/// worker thread.
+extern "C" fn request_diagnostics_dump(_signal: libc::c_int) {
+    DUMP_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Starts the worker that services SIGUSR1 diagnostics requests against the primary
+/// channel, so field debugging works without a binder client (e.g. in very early boot,
+/// before `dumpsys` is usable).
+fn start_signal_diagnostics(channel: Arc<Mutex<CommServiceChannel>>) {
+    let channel = HalChannel(channel);
+    thread::spawn(move || loop {
+        thread::sleep(DUMP_POLL_INTERVAL);
+        if DUMP_REQUESTED.swap(false, Ordering::Relaxed) {
+            log_diagnostics(&channel);
+        }
+    });
+}
+
+/// Logs the HAL's current diagnostics (build, connection state, counters) to logcat.
+fn log_diagnostics(channel: &HalChannel) {
+    info!("diagnostics: {BUILD_INFO}");
+    info!(
+        "diagnostics: queue_depth={} queue_high_water={}",
+        QUEUE_DEPTH.load(Ordering::Relaxed),
+        QUEUE_HIGH_WATER.load(Ordering::Relaxed)
+    );
+    // A channel busy with a transaction is reported as such rather than blocking the dump
+    // behind a slow VM.
+    let result = channel.try_with(|c| {
+        info!(
+            "diagnostics: endpoint={} connected={} vm_died={} consecutive_failures={} \
+             hal_info_sent={}",
+            c.endpoints[c.active_endpoint],
+            c.comm_service.is_some(),
+            c.vm_died.load(Ordering::Relaxed),
+            c.consecutive_failures,
+            c.hal_info_sent
+        );
+        info!(
+            "diagnostics: transactions={} errors={} request_bytes={} response_bytes={}",
+            c.stats.transactions.load(Ordering::Relaxed),
+            c.stats.errors.load(Ordering::Relaxed),
+            c.stats.request_bytes.load(Ordering::Relaxed),
+            c.stats.response_bytes.load(Ordering::Relaxed)
+        );
+        Ok(())
+    });
+    match result {
+        Ok(Some(())) => {}
+        Ok(None) => info!("diagnostics: channel busy; connection state unavailable."),
+        Err(e) => warn!("diagnostics: failed to inspect channel: {e:?}"),
+    }
+}
+
+/// System property holding the maximum number of transactions allowed in flight (holding
+/// or waiting for the channel lock) at once. Unset or zero keeps the default direct path,
+/// where callers wait on the lock without bound.
+const QUEUE_DEPTH_PROPERTY: &str = "keymint.hal.queue_depth";
+
+/// Transactions currently admitted, and the highest depth seen. Statics rather than
+/// `HalChannel` fields so every clone of the channel shares one queue.
+static QUEUE_DEPTH: AtomicU32 = AtomicU32::new(0);
+static QUEUE_HIGH_WATER: AtomicU32 = AtomicU32::new(0);
+
+/// Reads the configured queue depth limit once; zero means unbounded.
+fn configured_queue_depth() -> u32 {
+    static DEPTH: std::sync::OnceLock<u32> = std::sync::OnceLock::new();
+    *DEPTH.get_or_init(|| {
+        rustutils::system_properties::read(QUEUE_DEPTH_PROPERTY)
+            .ok()
+            .flatten()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0)
+    })
+}
+
 /// Helper struct to provide convenient access to the locked channel.
 struct HalChannel(Arc<Mutex<CommServiceChannel>>);
 
 impl HalChannel {
     /// Executes a closure with a mutable reference to the inner channel.
+    ///
+    /// When a queue depth limit is configured, admission is bounded: once that many
+    /// transactions are already holding or waiting for the lock, further callers fail
+    /// immediately with an overload error instead of piling up binder threads behind the
+    /// mutex.
     fn with<F, R>(&self, f: F) -> Result<R>
     where
         F: FnOnce(&mut CommServiceChannel) -> Result<R>,
     {
-        let mut channel = self.0.lock().map_err(|_| anyhow!("Mutex was poisoned"))?;
-        f(channel.deref_mut())
+        let limit = configured_queue_depth();
+        let depth = QUEUE_DEPTH.fetch_add(1, Ordering::Relaxed) + 1;
+        QUEUE_HIGH_WATER.fetch_max(depth, Ordering::Relaxed);
+        if limit > 0 && depth > limit {
+            QUEUE_DEPTH.fetch_sub(1, Ordering::Relaxed);
+            bail!("channel overloaded: {} transactions already queued (limit {limit})", depth - 1);
+        }
+        let result = match self.0.lock() {
+            Ok(mut channel) => f(channel.deref_mut()),
+            Err(_) => Err(anyhow!("Mutex was poisoned")),
+        };
+        QUEUE_DEPTH.fetch_sub(1, Ordering::Relaxed);
+        result
+    }
+
+    /// Non-blocking variant of `with` for diagnostic paths.
+    ///
+    /// Runs the closure only if the channel lock is free, returning `Ok(None)` when a
+    /// transaction holds it, so watchdog-style callers report "busy" instead of stalling
+    /// behind a slow VM. Admission bounding doesn't apply since nothing ever queues here.
+    fn try_with<F, R>(&self, f: F) -> Result<Option<R>>
+    where
+        F: FnOnce(&mut CommServiceChannel) -> Result<R>,
+    {
+        match self.0.try_lock() {
+            Ok(mut channel) => f(channel.deref_mut()).map(Some),
+            Err(std::sync::TryLockError::WouldBlock) => Ok(None),
+            Err(std::sync::TryLockError::Poisoned(_)) => Err(anyhow!("Mutex was poisoned")),
+        }
     }
 }
 
@@ -65,58 +1023,590 @@ impl From<CommServiceChannel> for HalChannel {
     }
 }
 
+/// System property that opts into exiting with `FAILURE_EXIT_CODE` instead of panicking when
+/// the service fails, so init's restart/backoff policy applies cleanly.
+const CLEAN_EXIT_PROPERTY: &str = "keymint.hal.clean_exit";
+const FAILURE_EXIT_CODE: i32 = 2;
+
 fn main() {
     if let Err(e) = inner_main() {
+        if rustutils::system_properties::read_bool(CLEAN_EXIT_PROPERTY, false).unwrap_or(false) {
+            error!("HAL service failed, exiting with code {FAILURE_EXIT_CODE}: {e:?}");
+            std::process::exit(FAILURE_EXIT_CODE);
+        }
         panic!("HAL service failed: {e:?}");
     }
 }
 
+#[derive(Parser, Debug)]
+struct Args {
+    /// Connect to the VM, run a single diagnostic transaction, print pass/fail, and exit
+    /// without registering services.
+    #[arg(long)]
+    check: bool,
+    /// Construct and register all AIDL service objects against a stub channel, then exit.
+    /// A wiring self-test for CI; no Trusty VM is needed or contacted.
+    #[arg(long)]
+    once: bool,
+    /// Serve the HALs from a captured channel trace instead of a live VM.
+    #[cfg(feature = "channel-trace")]
+    #[arg(long)]
+    replay: Option<std::path::PathBuf>,
+}
+
+/// System property holding a hex CPU affinity mask (e.g. "0x3" for CPUs 0-1) applied to
+/// the process before the binder thread pool starts, so all binder threads inherit it.
+/// Unset leaves scheduling unchanged. Pinning reduces scheduling jitter in the transaction
+/// path during latency benchmarking.
+const CPU_AFFINITY_PROPERTY: &str = "keymint.hal.cpu_affinity";
+
+/// Returns the set of online CPUs, parsed from sysfs range notation (e.g. "0-3,5").
+fn online_cpus() -> Result<Vec<usize>> {
+    let ranges = std::fs::read_to_string("/sys/devices/system/cpu/online")
+        .context("failed to read online CPU list")?;
+    let mut cpus = Vec::new();
+    for range in ranges.trim().split(',') {
+        match range.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = start.parse().context("bad online CPU range")?;
+                let end: usize = end.parse().context("bad online CPU range")?;
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(range.parse().context("bad online CPU entry")?),
+        }
+    }
+    Ok(cpus)
+}
+
+/// Applies the configured CPU affinity mask to the process, if one is set.
+///
+/// The mask is validated against the online CPUs: bits naming offline or nonexistent CPUs
+/// are an error rather than being silently dropped, since a typo'd mask would otherwise
+/// invalidate whatever latency measurement prompted the pinning.
+fn apply_cpu_affinity() -> Result<()> {
+    let Ok(Some(mask_text)) = rustutils::system_properties::read(CPU_AFFINITY_PROPERTY) else {
+        return Ok(());
+    };
+    let mask = u64::from_str_radix(mask_text.trim_start_matches("0x"), 16)
+        .with_context(|| format!("invalid CPU affinity mask: {mask_text}"))?;
+    if mask == 0 {
+        bail!("CPU affinity mask must name at least one CPU");
+    }
+    let online = online_cpus()?;
+    let cpus: Vec<usize> = (0..64).filter(|cpu| mask & (1 << cpu) != 0).collect();
+    if let Some(cpu) = cpus.iter().find(|cpu| !online.contains(cpu)) {
+        bail!("CPU affinity mask {mask_text} names CPU {cpu}, which is not online");
+    }
+
+    // SAFETY: `set` is a fully initialized cpu_set_t and pid 0 targets this process.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for &cpu in &cpus {
+            libc::CPU_SET(cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            bail!(
+                "sched_setaffinity failed: {:?}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+    info!("Pinned process to CPUs {cpus:?}.");
+    Ok(())
+}
+
+/// System property recording startup attempts as `<count>:<window start, epoch seconds>`.
+/// Written by the crash-loop guard; an absent or unparsable value starts a fresh window.
+const RESTART_HISTORY_PROPERTY: &str = "keymint.hal.restart_history";
+
+/// Startup attempts within `CRASH_LOOP_WINDOW` before the guard engages.
+const CRASH_LOOP_THRESHOLD: u32 = 5;
+const CRASH_LOOP_WINDOW: Duration = Duration::from_secs(60);
+
+/// How long a crash-looping HAL sleeps before continuing startup, giving the VM (or
+/// whatever is failing) time to recover instead of spamming init with restarts.
+const CRASH_LOOP_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Records this startup attempt and throttles if the HAL is restarting in a tight loop.
+///
+/// Repeated early-startup failures make init restart the service immediately, flooding the
+/// log. The guard keeps an attempt counter in a property; once the counter exceeds the
+/// threshold within the window, startup deliberately sleeps before proceeding so the
+/// restart cadence drops to something the rest of the system can live with.
+fn crash_loop_guard() {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let history = rustutils::system_properties::read(RESTART_HISTORY_PROPERTY)
+        .ok()
+        .flatten()
+        .and_then(|value| {
+            let (count, start) = value.split_once(':')?;
+            Some((count.parse::<u32>().ok()?, start.parse::<u64>().ok()?))
+        });
+    let (count, window_start) = match history {
+        Some((count, start)) if now.saturating_sub(start) < CRASH_LOOP_WINDOW.as_secs() => {
+            (count + 1, start)
+        }
+        _ => (1, now),
+    };
+    if let Err(e) = rustutils::system_properties::write(
+        RESTART_HISTORY_PROPERTY,
+        &format!("{count}:{window_start}"),
+    ) {
+        warn!("Failed to persist restart history: {e:?}");
+    }
+    if count > CRASH_LOOP_THRESHOLD {
+        error!(
+            "Crash loop detected: {count} startup attempts within {}s; sleeping {}s before \
+             continuing.",
+            CRASH_LOOP_WINDOW.as_secs(),
+            CRASH_LOOP_BACKOFF.as_secs()
+        );
+        thread::sleep(CRASH_LOOP_BACKOFF);
+    }
+}
+
+/// System property listing extra VMs to serve from this process, as comma-separated
+/// `instance=rpc_service` pairs (e.g. "strongbox=security_vm_strongbox_rpc"). The default
+/// instance is always served first. Unset means only the default instance.
+const EXTRA_INSTANCES_PROPERTY: &str = "keymint.hal.extra_instances";
+
+/// Whether a failure to bring up an extra instance is tolerated, leaving the remaining
+/// instances running. The default (primary) instance failing is always fatal.
+const EXTRA_INSTANCES_BEST_EFFORT_PROPERTY: &str = "keymint.hal.extra_instances_best_effort";
+
+/// System property overriding the binder service instance the primary HALs register under.
+const SERVICE_INSTANCE_PROPERTY: &str = "keymint.hal.service_instance";
+
+/// System property overriding the commservice RPC endpoint the primary channel connects to.
+const RPC_SERVICE_PROPERTY: &str = "keymint.hal.rpc_service";
+
+/// System property selecting the log level: `error`, `warn`, `info` or `debug`.
+const LOG_LEVEL_PROPERTY: &str = "keymint.hal.log_level";
+
+/// The tunables resolved once at startup, gathering the scattered property reads into one
+/// place so the effective configuration can be logged and inspected as a unit. Defaults
+/// match the compiled-in behavior; every field is overridable without recompiling.
+#[derive(Debug)]
+struct HalConfig {
+    log_level: log::LevelFilter,
+    log_json: bool,
+    /// Binder service instance the primary HALs register under.
+    instance: String,
+    /// Commservice RPC endpoint the primary channel connects to.
+    rpc_service: String,
+    max_size: usize,
+    queue_depth: u32,
+    idle_timeout: Option<Duration>,
+}
+
+impl HalConfig {
+    /// Reads the configuration from system properties, falling back to the defaults.
+    fn resolve() -> Self {
+        let log_level = match rustutils::system_properties::read(LOG_LEVEL_PROPERTY)
+            .ok()
+            .flatten()
+            .as_deref()
+        {
+            Some("error") => log::LevelFilter::Error,
+            Some("warn") => log::LevelFilter::Warn,
+            Some("debug") => log::LevelFilter::Debug,
+            Some("info") | None => log::LevelFilter::Info,
+            Some(other) => {
+                error!("Ignoring {LOG_LEVEL_PROPERTY}='{other}'; using info.");
+                log::LevelFilter::Info
+            }
+        };
+        let read_or = |property: &str, default: &str| {
+            rustutils::system_properties::read(property)
+                .ok()
+                .flatten()
+                .filter(|value| !value.is_empty())
+                .unwrap_or_else(|| default.to_owned())
+        };
+        Self {
+            log_level,
+            log_json: rustutils::system_properties::read_bool(LOG_JSON_PROPERTY, false)
+                .unwrap_or(false),
+            instance: read_or(SERVICE_INSTANCE_PROPERTY, SERVICE_INSTANCE),
+            rpc_service: read_or(RPC_SERVICE_PROPERTY, INTERNAL_RPC_SERVICE_NAME),
+            max_size: effective_max_size(),
+            queue_depth: configured_queue_depth(),
+            idle_timeout: idle_timeout(),
+        }
+    }
+}
+
+/// The resolved configuration, read once since properties cannot meaningfully change
+/// mid-run.
+fn hal_config() -> &'static HalConfig {
+    static CONFIG: std::sync::OnceLock<HalConfig> = std::sync::OnceLock::new();
+    CONFIG.get_or_init(HalConfig::resolve)
+}
+
+/// One VM served by this process: the binder service instance suffix its HALs register
+/// under, and the commservice RPC endpoint its channel connects to.
+struct VmIdentity {
+    instance: String,
+    rpc_service: String,
+}
+
+/// Returns the VMs to serve: the default instance plus any configured extras, malformed
+/// entries logged and skipped.
+fn configured_instances() -> Vec<VmIdentity> {
+    let config = hal_config();
+    let mut instances = vec![VmIdentity {
+        instance: config.instance.clone(),
+        rpc_service: config.rpc_service.clone(),
+    }];
+    if let Ok(Some(extras)) = rustutils::system_properties::read(EXTRA_INSTANCES_PROPERTY) {
+        for entry in extras.split(',').filter(|entry| !entry.is_empty()) {
+            match entry.split_once('=') {
+                Some((instance, rpc_service)) if !instance.is_empty() && !rpc_service.is_empty() => {
+                    instances.push(VmIdentity {
+                        instance: instance.to_owned(),
+                        rpc_service: rpc_service.to_owned(),
+                    });
+                }
+                _ => error!("Ignoring malformed {EXTRA_INSTANCES_PROPERTY} entry '{entry}'."),
+            }
+        }
+    }
+    instances
+}
+
 fn inner_main() -> Result<()> {
+    let args = Args::parse();
     setup_logging_and_panic_hook();
+    crash_loop_guard();
 
     if cfg!(feature = "nonsecure") {
         warn!("Non-secure Trusty KM HAL service is starting.");
     } else {
         info!("Trusty KM HAL service is starting.");
     }
+    info!("Resolved configuration: {:?}", hal_config());
+    log_event(
+        "startup",
+        &[
+            ("build_id", BUILD_INFO.build_id),
+            ("nonsecure", if BUILD_INFO.nonsecure { "true" } else { "false" }),
+            ("service_instance", BUILD_INFO.service_instance),
+        ],
+    );
+
+    // Pin before the thread pool starts so binder threads inherit the affinity. A bad
+    // debug property must not keep the HAL from coming up, so fall back to the default
+    // affinity on error.
+    if let Err(e) = apply_cpu_affinity() {
+        warn!("Ignoring CPU affinity setting, continuing with default affinity: {e:?}");
+    }
 
     info!("Starting thread pool.");
     ProcessState::start_thread_pool();
 
+    if args.once {
+        // Self-test against the same instance the real services would register under.
+        return run_once(&hal_config().instance);
+    }
+
+    #[cfg(feature = "channel-trace")]
+    if let Some(path) = &args.replay {
+        let channel = Arc::new(Mutex::new(ReplayChannel::load(path)?));
+        register_binder_services(&channel, ALL_HALS, &hal_config().instance)?;
+        info!("Serving KeyMint HALs from channel trace replay. Joining thread pool now.");
+        ProcessState::join_thread_pool();
+        bail!("Binder thread pool exited unexpectedly, terminating HAL service.");
+    }
+
+    let instances = configured_instances();
     // TODO(b/429217397): Use a proper way to register an accessor and get the internal RPC
     // service via accessor here.
-    let _accessor_provider = AccessorProvider::new(&[INTERNAL_RPC_SERVICE_NAME.to_owned()], |s| {
-        binder::wait_for_service(ACCESSOR_SERVICE_NAME)
-            .and_then(|service| binder::Accessor::from_binder(s, service))
-    })
-    .ok_or(anyhow!("failed to create accessor provider"))?;
-    let comm_service = binder::wait_for_interface(INTERNAL_RPC_SERVICE_NAME)
+    let rpc_services: Vec<String> =
+        instances.iter().map(|identity| identity.rpc_service.clone()).collect();
+    let _accessor_provider = create_accessor_provider(&rpc_services)?;
+
+    let best_effort = rustutils::system_properties::read_bool(
+        EXTRA_INSTANCES_BEST_EFFORT_PROPERTY,
+        false,
+    )
+    .unwrap_or(false);
+    for (index, identity) in instances.iter().enumerate() {
+        let primary = index == 0;
+        if primary && args.check {
+            // --check only exercises the primary VM's channel.
+            let channel = connect_instance(identity)?;
+            return run_check(&channel, &identity.instance);
+        }
+        match bring_up_instance(identity, primary) {
+            Ok(()) => {}
+            // The primary instance must come up; extras may be best-effort.
+            Err(e) if !primary && best_effort => {
+                error!("Failed to bring up instance '{}': {e:?}; continuing.", identity.instance);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    info!("Successfully registered KeyMint HAL services. Joining thread pool now.");
+
+    ProcessState::join_thread_pool();
+    bail!("Binder thread pool exited unexpectedly, terminating HAL service.");
+}
+
+/// Builds the channel to one VM's commservice and links its death recipient.
+fn connect_instance(identity: &VmIdentity) -> Result<HalChannel> {
+    let mut endpoints = vec![identity.rpc_service.clone()];
+    // The failover backup only applies to the primary instance; extra instances name their
+    // endpoint explicitly.
+    if identity.instance == hal_config().instance {
+        if let Ok(Some(backup)) = rustutils::system_properties::read(BACKUP_RPC_SERVICE_PROPERTY)
+        {
+            info!("Backup commservice instance configured: {backup}");
+            endpoints.push(backup);
+        }
+    }
+    let comm_service = CommServiceChannel::connect_endpoint(&endpoints[0])
         .context("failed to get ICommService interface from accessor")?;
-    let channel: HalChannel = CommServiceChannel { comm_service }.into();
+    let stats = Arc::new(ChannelStats::default());
+    #[cfg(feature = "metrics")]
+    if identity.instance == hal_config().instance {
+        start_metrics_exporter(stats.clone());
+    }
+    let channel: HalChannel = CommServiceChannel {
+        comm_service: Some(comm_service),
+        vm_died: Arc::new(AtomicBool::new(false)),
+        death_recipient: None,
+        stats,
+        last_used: Instant::now(),
+        endpoints,
+        active_endpoint: 0,
+        consecutive_failures: 0,
+        hal_info_sent: false,
+    }
+    .into();
+    channel.with(|c| {
+        c.link_death_recipient();
+        Ok(())
+    })?;
+    Ok(channel)
+}
+
+/// System property that adds a round-trip self-test transaction to the startup health
+/// gate, on top of the always-on connectivity and HAL info gates.
+const STARTUP_SELF_TEST_PROPERTY: &str = "keymint.hal.startup_self_test";
+
+/// Connects, verifies and registers the KeyMint services for one VM.
+///
+/// Every health gate runs strictly before `register_binder_services()`, so `checkService`
+/// only ever succeeds for a HAL whose channel has already proven it can serve requests —
+/// clients never race a half-ready HAL.
+fn bring_up_instance(identity: &VmIdentity, primary: bool) -> Result<()> {
+    let channel = connect_instance(identity)?;
+    info!("Startup gate 1/3 passed for '{}': commservice connected.", identity.instance);
+
+    if primary {
+        if let Some(timeout) = idle_timeout() {
+            info!("Idle disconnect enabled with timeout {timeout:?}.");
+            start_idle_disconnect(channel.0.clone(), timeout);
+        }
+        start_signal_diagnostics(channel.0.clone());
+    }
+
+    // Verify who we're talking to before any HAL info is sent or services published.
+    verify_channel_identity(&channel)?;
+
+    let probe_channel = HalChannel(channel.0.clone());
+    thread::spawn(move || {
+        negotiate_reply_size(&probe_channel);
+        probe_capabilities(&probe_channel);
+    });
 
     #[cfg(feature = "nonsecure")]
     kmr_hal_nonsecure::send_boot_info_and_attestation_id_info(&channel.0)?;
 
-    register_binder_services(&channel.0, ALL_HALS, SERVICE_INSTANCE)?;
+    // Send the HAL service information to the TA.
+    channel.with(|c| c.send_hal_info())?;
+    info!("Startup gate 2/3 passed for '{}': HAL info accepted.", identity.instance);
+
+    if rustutils::system_properties::read_bool(STARTUP_SELF_TEST_PROPERTY, false)
+        .unwrap_or(false)
+    {
+        channel
+            .with(|c| {
+                c.execute(CAPABILITIES_PROBE_REQUEST)
+                    .map_err(|e| anyhow!("self-test transaction failed: {e:?}"))
+            })
+            .context("startup self-test failed; not registering services")?;
+        info!("Startup gate 3/3 passed for '{}': self-test transaction served.", identity.instance);
+    } else {
+        info!("Startup gate 3/3 skipped for '{}': self-test not configured.", identity.instance);
+    }
 
-    // Send the HAL service information to the TA
-    channel.with(|c| send_hal_info(c).context("failed to populate HAL info"))?;
+    register_binder_services(&channel.0, ALL_HALS, &identity.instance)?;
+    report_service_status(&identity.instance);
 
-    info!("Successfully registered KeyMint HAL services. Joining thread pool now.");
+    log_event("services_registered", &[("service_instance", &identity.instance)]);
+    Ok(())
+}
 
-    ProcessState::join_thread_pool();
-    bail!("Binder thread pool exited unexpectedly, terminating HAL service.");
+/// Creates the accessor provider for the served RPC services, retrying a bounded number of
+/// times since the accessor service may not be registered yet during early boot.
+fn create_accessor_provider(rpc_services: &[String]) -> Result<AccessorProvider> {
+    for attempt in 1..=ACCESSOR_PROVIDER_ATTEMPTS {
+        let provider = AccessorProvider::new(rpc_services, |s| {
+            let service = binder::wait_for_service(ACCESSOR_SERVICE_NAME)?;
+            let accessor = binder::Accessor::from_binder(s, service);
+            if accessor.is_none() {
+                error!(
+                    "Service {ACCESSOR_SERVICE_NAME} is registered but does not implement \
+                     IAccessor."
+                );
+            }
+            accessor
+        });
+        if let Some(provider) = provider {
+            return Ok(provider);
+        }
+        warn!(
+            "Failed to create accessor provider (attempt {attempt} of \
+             {ACCESSOR_PROVIDER_ATTEMPTS})."
+        );
+        if attempt < ACCESSOR_PROVIDER_ATTEMPTS {
+            thread::sleep(ACCESSOR_PROVIDER_RETRY_DELAY);
+        }
+    }
+    Err(anyhow!(
+        "failed to create accessor provider after {ACCESSOR_PROVIDER_ATTEMPTS} attempts \
+         (accessor service: {ACCESSOR_SERVICE_NAME}, internal RPC service: \
+         {INTERNAL_RPC_SERVICE_NAME})"
+    ))
+}
+
+/// System property naming the Unix socket path the metrics exporter listens on. When unset,
+/// nothing is exposed.
+#[cfg(feature = "metrics")]
+const METRICS_SOCKET_PROPERTY: &str = "keymint.hal.metrics_socket";
+
+/// Serves `ChannelStats` in prometheus text format on a local Unix socket.
+///
+/// The exporter runs on its own thread so a slow or stuck scraper can never block
+/// transactions.
+#[cfg(feature = "metrics")]
+fn start_metrics_exporter(stats: Arc<ChannelStats>) {
+    use std::io::Write;
+    use std::os::unix::net::UnixListener;
+
+    let path = match rustutils::system_properties::read(METRICS_SOCKET_PROPERTY) {
+        Ok(Some(path)) => path,
+        _ => {
+            info!("Metrics exporter disabled: {METRICS_SOCKET_PROPERTY} is not set.");
+            return;
+        }
+    };
+    thread::spawn(move || {
+        // Remove any stale socket left behind by a previous instance.
+        let _ = std::fs::remove_file(&path);
+        let listener = match UnixListener::bind(&path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to bind metrics socket at {path}: {e:?}");
+                return;
+            }
+        };
+        info!("Metrics exporter listening on {path}.");
+        for stream in listener.incoming() {
+            match stream {
+                Ok(mut stream) => {
+                    if let Err(e) = stream.write_all(stats.render().as_bytes()) {
+                        warn!("Failed to write metrics: {e:?}");
+                    }
+                }
+                Err(e) => warn!("Failed to accept metrics connection: {e:?}"),
+            }
+        }
+    });
+}
+
+/// System property selecting single-line JSON for key log events. Defaults to the plain
+/// text format when unset.
+const LOG_JSON_PROPERTY: &str = "keymint.hal.log_json";
+
+/// Whether key events are emitted as JSON; latched once at startup.
+static LOG_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Appends `text` to `out` with JSON string escaping.
+///
+/// Only allocation-free pushes, so it is safe to use inside the panic hook.
+fn json_escape_into(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => out.push(' '),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Logs a key event with stable field names.
+///
+/// In JSON mode the event is a single-line object `{"event":...,"field":...}` suitable for
+/// structured ingestion; otherwise it renders as the usual free-form text.
+fn log_event(event: &str, fields: &[(&str, &str)]) {
+    if LOG_JSON.load(Ordering::Relaxed) {
+        let mut line = String::from("{\"event\":\"");
+        json_escape_into(&mut line, event);
+        line.push('"');
+        for (name, value) in fields {
+            line.push_str(",\"");
+            json_escape_into(&mut line, name);
+            line.push_str("\":\"");
+            json_escape_into(&mut line, value);
+            line.push('"');
+        }
+        line.push('}');
+        info!("{line}");
+    } else {
+        let rendered: Vec<String> = fields
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect();
+        info!("{event}: {}", rendered.join(" "));
+    }
 }
 
 fn setup_logging_and_panic_hook() {
     android_logger::init_once(
         android_logger::Config::default()
             .with_tag("keymint-hal-trusty-vm")
-            .with_max_level(log::LevelFilter::Info)
+            // The logger filter is left wide open; the effective level is the global
+            // `log::set_max_level()` applied below from the resolved config.
+            .with_max_level(log::LevelFilter::Trace)
             .with_log_buffer(android_logger::LogId::System),
     );
+    // Resolving the config happens while the global max level is still `Trace`, so anything
+    // `HalConfig::resolve()` logs reaches logcat before the level is narrowed here.
+    let config = hal_config();
+    log::set_max_level(config.log_level);
+    LOG_JSON.store(config.log_json, Ordering::Relaxed);
     // In case of a panic, log it before the process terminates.
     panic::set_hook(Box::new(|panic_info| {
-        error!("PANIC: {panic_info}");
+        if LOG_JSON.load(Ordering::Relaxed) {
+            let mut line = String::from("{\"event\":\"panic\",\"info\":\"");
+            json_escape_into(&mut line, &panic_info.to_string());
+            line.push_str("\"}");
+            error!("{line}");
+        } else {
+            error!("PANIC: {panic_info}");
+        }
     }));
+    // SAFETY: the handler only performs an atomic store, which is async-signal-safe.
+    unsafe {
+        libc::signal(libc::SIGUSR1, request_diagnostics_dump as libc::sighandler_t);
+    }
 }
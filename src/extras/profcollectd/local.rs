@@ -0,0 +1,526 @@
+//
+// Copyright (C) 2020 The Android Open Source Project
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Client-side implementations of the extended profcollectctl features.
+//!
+//! `libprofcollectd` exposes a deliberately small surface (trace, process,
+//! report, reset); everything here is layered on top of it with plain
+//! filesystem and system property operations so the daemon interface stays
+//! minimal.
+
+use anyhow::{Context, Result};
+use flate2::{Compression, write::GzEncoder};
+use rustutils::system_properties;
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::PROFCOLLECTD_DATA_DIRS;
+
+/// Directory the daemon stores raw traces in.
+fn trace_dir() -> &'static Path {
+    Path::new(PROFCOLLECTD_DATA_DIRS[0])
+}
+
+/// Directory the daemon stores processed profiles in.
+fn output_dir() -> &'static Path {
+    Path::new(PROFCOLLECTD_DATA_DIRS[1])
+}
+
+/// Logger backing `init_logging_to_file`.
+struct FileLogger(Mutex<fs::File>);
+
+impl log::Log for FileLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        if let Ok(mut file) = self.0.lock() {
+            let _ = writeln!(file, "{} {}: {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.0.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Routes tool and library logs to the given file instead of the default sink.
+pub fn init_logging_to_file(path: &Path) -> Result<()> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open log file {}.", path.display()))?;
+    log::set_boxed_logger(Box::new(FileLogger(Mutex::new(file))))
+        .context("Logging was already initialized.")?;
+    log::set_max_level(log::LevelFilter::Debug);
+    Ok(())
+}
+
+/// Parameters for the trace variants layered on top of `trace_system`.
+pub struct TraceOptions {
+    /// Tag to store the trace under.
+    pub tag: String,
+    /// Trace duration in milliseconds.
+    pub duration_ms: i32,
+    /// Note stored alongside the produced trace files.
+    pub note: Option<String>,
+}
+
+/// Subdirectory of the trace directory holding `--annotate` notes, one file per
+/// trace, named after the trace file the note belongs to.
+const NOTES_SUBDIR: &str = ".notes";
+
+/// Lists the plain files in a directory; a missing directory reads as empty.
+fn list_files(dir: &Path) -> Result<HashSet<OsString>> {
+    let mut files = HashSet::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}.", dir.display())),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.insert(entry.file_name());
+        }
+    }
+    Ok(files)
+}
+
+/// Moves a file, falling back to copy-and-remove when the rename would cross
+/// filesystems.
+fn move_file(src: &Path, dest: &Path) -> Result<()> {
+    if fs::rename(src, dest).is_ok() {
+        return Ok(());
+    }
+    fs::copy(src, dest)
+        .with_context(|| format!("Failed to move {} to {}.", src.display(), dest.display()))?;
+    fs::remove_file(src).with_context(|| format!("Failed to remove {}.", src.display()))?;
+    Ok(())
+}
+
+/// Runs one capture and returns the trace files it produced, writing the note
+/// sidecar for each if one was requested.
+fn capture(options: &TraceOptions) -> Result<Vec<PathBuf>> {
+    let before = list_files(trace_dir())?;
+    libprofcollectd::trace_system(&options.tag, options.duration_ms)?;
+    let mut produced = Vec::new();
+    for name in list_files(trace_dir())? {
+        if !before.contains(&name) {
+            produced.push(trace_dir().join(name));
+        }
+    }
+    if let Some(note) = &options.note {
+        let notes_dir = trace_dir().join(NOTES_SUBDIR);
+        fs::create_dir_all(&notes_dir).context("Failed to create the notes directory.")?;
+        for trace in &produced {
+            let mut note_name = trace.file_name().unwrap_or_default().to_os_string();
+            note_name.push(".note");
+            fs::write(notes_dir.join(note_name), note)
+                .context("Failed to write the trace note.")?;
+        }
+    }
+    Ok(produced)
+}
+
+/// Captures a trace, storing the note alongside it if one was given.
+pub fn trace_with_options(options: TraceOptions) -> Result<()> {
+    capture(&options).map(|_| ())
+}
+
+/// Milliseconds traced per chunk while waiting for an `--until-event` event.
+const UNTIL_EVENT_CHUNK_MS: i32 = 1000;
+
+/// Captures back-to-back trace chunks until the named system property reads as
+/// set, or the timeout expires. The fixed duration in `options` is ignored;
+/// each chunk runs for `UNTIL_EVENT_CHUNK_MS`, and the note (if any) is stored
+/// with every chunk.
+pub fn trace_until(options: TraceOptions, event: &str, timeout_ms: i32) -> Result<()> {
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms.max(0) as u64);
+    let chunk = TraceOptions { duration_ms: UNTIL_EVENT_CHUNK_MS, ..options };
+    loop {
+        capture(&chunk)?;
+        if matches!(system_properties::read(event)?.as_deref(), Some("1") | Some("true")) {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Ok(());
+        }
+    }
+}
+
+/// Captures a trace and moves the produced files into `dir` instead of the
+/// store.
+pub fn trace_to_dir(dir: &Path, options: TraceOptions) -> Result<()> {
+    let produced = capture(&options)?;
+    anyhow::ensure!(!produced.is_empty(), "The capture produced no trace files.");
+    for trace in &produced {
+        let name = trace.file_name().context("Trace file has no name.")?;
+        move_file(trace, &dir.join(name))?;
+    }
+    Ok(())
+}
+
+/// Options for `process_with_options`.
+pub struct ProcessOptions {
+    /// Only process traces collected at or after this time.
+    pub since: Option<SystemTime>,
+    /// Put the raw traces back after conversion instead of letting the daemon
+    /// consume them.
+    pub keep_traces: bool,
+}
+
+/// Sibling directory files are parked in while a filtered operation runs, kept
+/// outside the data directory so the daemon never sweeps it up.
+fn hold_dir(dir: &Path) -> PathBuf {
+    let mut held = dir.as_os_str().to_os_string();
+    held.push(".hold");
+    PathBuf::from(held)
+}
+
+/// Moves the files in `dir` that `exclude` matches into the hold directory,
+/// returning their names so they can be restored afterwards.
+fn hold_files(
+    dir: &Path,
+    exclude: impl Fn(&OsStr, &fs::Metadata) -> bool,
+) -> Result<Vec<OsString>> {
+    let mut held = Vec::new();
+    for name in list_files(dir)? {
+        let path = dir.join(&name);
+        let metadata = fs::metadata(&path)?;
+        if exclude(&name, &metadata) {
+            if held.is_empty() {
+                fs::create_dir_all(hold_dir(dir))?;
+            }
+            move_file(&path, &hold_dir(dir).join(&name))?;
+            held.push(name);
+        }
+    }
+    Ok(held)
+}
+
+/// Moves previously held files back into `dir`.
+fn restore_files(dir: &Path, held: &[OsString]) -> Result<()> {
+    for name in held {
+        move_file(&hold_dir(dir).join(name), &dir.join(name))?;
+    }
+    let _ = fs::remove_dir(hold_dir(dir));
+    Ok(())
+}
+
+/// Processes stored traces, optionally restricted to those collected since the
+/// given time, and returns how many converted and how many were left behind.
+pub fn process_with_options(options: ProcessOptions) -> Result<(usize, usize)> {
+    let held = match options.since {
+        Some(since) => hold_files(trace_dir(), |_, metadata| {
+            metadata.modified().map(|modified| modified < since).unwrap_or(false)
+        })?,
+        None => Vec::new(),
+    };
+    // For keep-traces, copy (rather than move) the candidates aside so the
+    // daemon still converts them; the copies are moved back afterwards.
+    let kept: Vec<OsString> = if options.keep_traces {
+        let names: Vec<OsString> = list_files(trace_dir())?.into_iter().collect();
+        if !names.is_empty() {
+            fs::create_dir_all(hold_dir(trace_dir()))?;
+        }
+        for name in &names {
+            fs::copy(trace_dir().join(name), hold_dir(trace_dir()).join(name))
+                .with_context(|| format!("Failed to copy {}.", name.to_string_lossy()))?;
+        }
+        names
+    } else {
+        Vec::new()
+    };
+    let before = list_files(trace_dir())?.len();
+    let result = libprofcollectd::process();
+    let after = list_files(trace_dir())?.len();
+    restore_files(trace_dir(), &kept)?;
+    restore_files(trace_dir(), &held)?;
+    result?;
+    // The daemon consumes traces it converts; whatever it left behind failed.
+    Ok((before.saturating_sub(after), after))
+}
+
+/// Device and build metadata stamped into a report.
+#[derive(Clone)]
+pub struct ReportMetadata {
+    /// Serial number of the device the profiles came from.
+    pub device_id: String,
+    /// Build fingerprint the profiles were collected on.
+    pub build_fingerprint: String,
+    /// Primary ABI of the device.
+    pub abi: String,
+    /// Kernel version string.
+    pub kernel_version: String,
+}
+
+/// Name of the metadata file bundled into a report.
+const METADATA_FILE: &str = "report_metadata.txt";
+
+/// Creates a report with the given metadata bundled in.
+///
+/// The metadata is written into the output directory for the duration of the
+/// call so the report step sweeps it up with the profiles.
+pub fn report_with_metadata(metadata: ReportMetadata) -> Result<String> {
+    fs::create_dir_all(output_dir())?;
+    let path = output_dir().join(METADATA_FILE);
+    fs::write(
+        &path,
+        format!(
+            "device_id: {}\nbuild_fingerprint: {}\nabi: {}\nkernel_version: {}\n",
+            metadata.device_id, metadata.build_fingerprint, metadata.abi, metadata.kernel_version
+        ),
+    )
+    .context("Failed to write the report metadata.")?;
+    let result = libprofcollectd::report();
+    let _ = fs::remove_file(&path);
+    Ok(result?)
+}
+
+/// Generates a report from whatever is currently in the output directory.
+fn report_current(metadata: Option<ReportMetadata>) -> Result<String> {
+    match metadata {
+        Some(metadata) => report_with_metadata(metadata),
+        None => Ok(libprofcollectd::report()?),
+    }
+}
+
+/// Creates a report containing only profiles modified inside `[start, end]`.
+/// Returns `None` when no profile falls in the window.
+pub fn report_window(
+    start: SystemTime,
+    end: SystemTime,
+    metadata: Option<ReportMetadata>,
+) -> Result<Option<String>> {
+    let held = hold_files(output_dir(), |_, file| {
+        file.modified().map(|modified| modified < start || modified > end).unwrap_or(false)
+    })?;
+    if list_files(output_dir())?.is_empty() {
+        restore_files(output_dir(), &held)?;
+        return Ok(None);
+    }
+    let result = report_current(metadata);
+    restore_files(output_dir(), &held)?;
+    result.map(Some)
+}
+
+/// Creates a report of at most roughly `max_bytes`, preferring newer profiles.
+/// Returns the report path and how many profiles were included and omitted.
+///
+/// The bound is applied to the uncompressed profile sizes, so the resulting
+/// report is usually smaller than requested.
+pub fn report_bounded(
+    max_bytes: u64,
+    metadata: Option<ReportMetadata>,
+) -> Result<(String, usize, usize)> {
+    let mut profiles = Vec::new();
+    for name in list_files(output_dir())? {
+        let info = fs::metadata(output_dir().join(&name))?;
+        profiles.push((name, info.modified()?, info.len()));
+    }
+    // Newest profiles first, so the budget drops the oldest data.
+    profiles.sort_by_key(|(_, modified, _)| std::cmp::Reverse(*modified));
+    let mut total = 0u64;
+    let mut omit = HashSet::new();
+    let mut included = 0usize;
+    for (name, _, len) in profiles {
+        if total + len <= max_bytes {
+            total += len;
+            included += 1;
+        } else {
+            omit.insert(name);
+        }
+    }
+    anyhow::ensure!(included > 0, "No profile fits within {} bytes.", max_bytes);
+    let held = hold_files(output_dir(), |name, _| omit.contains(name))?;
+    let result = report_current(metadata);
+    restore_files(output_dir(), &held)?;
+    Ok((result?, included, omit.len()))
+}
+
+/// Assembles a report directory from a pulled data directory: the profile files
+/// plus a manifest listing their sizes, created under the input directory.
+pub fn report_offline(input_dir: &Path) -> Result<String> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let files = list_files(input_dir)?;
+    anyhow::ensure!(!files.is_empty(), "No profiles found in {}.", input_dir.display());
+    let dest = input_dir.join(format!("report_{}", timestamp));
+    fs::create_dir(&dest).with_context(|| format!("Failed to create {}.", dest.display()))?;
+    let mut names: Vec<OsString> = files.into_iter().collect();
+    names.sort();
+    let mut manifest = String::new();
+    for name in names {
+        let bytes = fs::copy(input_dir.join(&name), dest.join(&name))
+            .with_context(|| format!("Failed to copy {}.", name.to_string_lossy()))?;
+        manifest.push_str(&format!("{} {}\n", bytes, name.to_string_lossy()));
+    }
+    fs::write(dest.join("MANIFEST.txt"), manifest).context("Failed to write the manifest.")?;
+    Ok(dest.display().to_string())
+}
+
+/// Compresses a produced report with gzip, replacing it with a `.gz` sibling,
+/// and returns the new path.
+pub fn compress_report_gzip(path: &str) -> Result<String> {
+    let source = Path::new(path);
+    let mut compressed_name = source.as_os_str().to_os_string();
+    compressed_name.push(".gz");
+    let dest = PathBuf::from(compressed_name);
+    let mut input = fs::File::open(source)
+        .with_context(|| format!("Failed to open {}.", source.display()))?;
+    let output = fs::File::create(&dest)
+        .with_context(|| format!("Failed to create {}.", dest.display()))?;
+    let mut encoder = GzEncoder::new(output, Compression::default());
+    std::io::copy(&mut input, &mut encoder)
+        .with_context(|| format!("Failed to compress {}.", source.display()))?;
+    encoder.finish().context("Failed to finish the compressed report.")?;
+    fs::remove_file(source)
+        .with_context(|| format!("Failed to remove {}.", source.display()))?;
+    Ok(dest.display().to_string())
+}
+
+/// Removes stored files older than `cutoff` across the data directories,
+/// returning how many files and bytes were removed.
+pub fn prune(cutoff: SystemTime) -> Result<(usize, u64)> {
+    let mut files = 0usize;
+    let mut bytes = 0u64;
+    for dir in PROFCOLLECTD_DATA_DIRS {
+        let dir = Path::new(dir);
+        for name in list_files(dir)? {
+            let path = dir.join(name);
+            let metadata = fs::metadata(&path)?;
+            if metadata.modified()? < cutoff {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}.", path.display()))?;
+                files += 1;
+                bytes += metadata.len();
+            }
+        }
+    }
+    Ok((files, bytes))
+}
+
+/// Copies a report and the raw traces into the `dest` directory, creating it.
+pub fn bundle_export(report_path: &str, dest: &Path) -> Result<()> {
+    let report = Path::new(report_path);
+    let name = report.file_name().context("Report path has no file name.")?;
+    fs::create_dir_all(dest)
+        .with_context(|| format!("Failed to create {}.", dest.display()))?;
+    fs::copy(report, dest.join(name))
+        .with_context(|| format!("Failed to copy {}.", report.display()))?;
+    let traces = dest.join("traces");
+    fs::create_dir_all(&traces)?;
+    for trace in list_files(trace_dir())? {
+        fs::copy(trace_dir().join(&trace), traces.join(&trace))
+            .with_context(|| format!("Failed to copy {}.", trace.to_string_lossy()))?;
+    }
+    Ok(())
+}
+
+/// The name a trace imports under: its modification time as the timestamp, the
+/// requested tag, and the original extension.
+pub fn import_file_name(path: &Path, tag: &str) -> Result<String> {
+    let metadata =
+        fs::metadata(path).with_context(|| format!("Failed to read {}.", path.display()))?;
+    let timestamp = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .with_context(|| format!("{} has no extension.", path.display()))?;
+    Ok(format!("{}_{}.{}", timestamp, tag, extension))
+}
+
+/// Copies an external trace into the store under the given tag.
+pub fn import_trace(path: &Path, tag: &str) -> Result<()> {
+    fs::create_dir_all(trace_dir())?;
+    fs::copy(path, trace_dir().join(import_file_name(path, tag)?))?;
+    Ok(())
+}
+
+/// Workload timings from `measure_overhead`, in milliseconds.
+pub struct Overhead {
+    /// Workload runtime without tracing.
+    pub baseline_ms: f64,
+    /// Workload runtime while a trace was running.
+    pub traced_ms: f64,
+}
+
+/// Iterations of the benchmark workload, sized to run for a few seconds.
+const BENCH_ITERATIONS: u64 = 200_000_000;
+
+/// A CPU-bound workload whose result is kept so it cannot be optimized out.
+fn bench_workload() -> u64 {
+    let mut state = 0x9e3779b97f4a7c15u64;
+    for _ in 0..BENCH_ITERATIONS {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+    }
+    std::hint::black_box(state)
+}
+
+/// Times one run of the benchmark workload.
+fn time_workload() -> f64 {
+    let start = Instant::now();
+    bench_workload();
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Times the benchmark workload with and without a trace running.
+pub fn measure_overhead() -> Result<Overhead> {
+    let baseline_ms = time_workload();
+    // Trace a little longer than the baseline so the whole traced run is
+    // covered.
+    let duration_ms = (baseline_ms * 1.5) as i32 + 1000;
+    let tracer = std::thread::spawn(move || libprofcollectd::trace_system("bench", duration_ms));
+    let traced_ms = time_workload();
+    tracer
+        .join()
+        .map_err(|_| anyhow::anyhow!("The trace thread panicked."))?
+        .context("Failed to trace during the benchmark.")?;
+    Ok(Overhead { baseline_ms, traced_ms })
+}
+
+/// Bytes of synthetic payload `generate_synthetic` writes.
+const SYNTHETIC_SIZE: usize = 4096;
+
+/// Writes a clearly labelled synthetic trace file into the store.
+pub fn generate_synthetic(tag: &str) -> Result<()> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let path = trace_dir().join(format!("{}_{}.trace", timestamp, tag));
+    let mut data = Vec::with_capacity(SYNTHETIC_SIZE);
+    data.extend_from_slice(b"PROFCOLLECT SYNTHETIC TRACE\n");
+    // Deterministic filler so the file has non-trivial contents.
+    let mut state = 0x9e3779b97f4a7c15u64;
+    while data.len() < SYNTHETIC_SIZE {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        data.extend_from_slice(&state.to_le_bytes());
+    }
+    fs::create_dir_all(trace_dir())?;
+    fs::write(&path, data).with_context(|| format!("Failed to write {}.", path.display()))?;
+    Ok(())
+}
@@ -21,9 +21,55 @@ use clap::{Args, Parser, Subcommand};
 use flags_rust::GetServerConfigurableFlag;
 use rustutils::system_properties;
 
+mod local;
+
+/// Property namespace holding the persisted enabled state.
+const DEFAULT_PROPERTY_NAMESPACE: &str = "persist.profcollectd";
+/// Server configurable flag namespace controlling collection.
+const DEFAULT_FLAG_NAMESPACE: &str = "profcollect_native_boot";
+/// Name of the enabled property/flag within the respective namespaces.
+const ENABLED_NAME: &str = "enabled";
+
+/// Namespaces that `set-property` may read or write. Keeping this list tight prevents the
+/// override from turning into a generic system property writer.
+const ALLOWED_PROPERTY_NAMESPACES: &[&str] = &["persist.profcollectd", "persist.profcollectd.test"];
+const ALLOWED_FLAG_NAMESPACES: &[&str] = &["profcollect_native_boot", "profcollect_native_test"];
+
+/// Directories holding profcollectd state, used to preview what `reset` would remove.
+const PROFCOLLECTD_DATA_DIRS: &[&str] = &[
+    "/data/misc/profcollectd/trace",
+    "/data/misc/profcollectd/output",
+    "/data/misc/profcollectd/report",
+];
+
 #[derive(Parser)]
 #[command(about = "Command interface for profcollectd", long_about = None)]
 struct Cli {
+    /// Print what the subcommand would do without performing any action.
+    #[arg(long = "dry-run", global = true)]
+    dry_run: bool,
+    /// Write tool and library logs to the given file instead of the default sink, leaving
+    /// stdout to carry only command output.
+    #[arg(long = "log-to", global = true)]
+    log_to: Option<std::path::PathBuf>,
+    /// Run without the profcollectd daemon, operating directly on a data directory. Only
+    /// read-only commands support this; commands that drive the daemon fail.
+    #[arg(long = "no-daemon", global = true)]
+    no_daemon: bool,
+    /// Data directory to operate on instead of the on-device store, for analyzing a
+    /// pulled directory on a workstation.
+    #[arg(long = "input-dir", global = true, requires = "no_daemon")]
+    input_dir: Option<std::path::PathBuf>,
+    /// Print a JSON Schema describing every `--format json` output, then exit.
+    #[arg(long = "json-schema", global = true, hide = true)]
+    json_schema: bool,
+    /// Retry the command this many times if it fails with a transient daemon error.
+    #[arg(long = "retries", global = true, default_value_t = 0)]
+    retries: u32,
+    /// Delay between retries (e.g. "5s" or "500" for seconds); only meaningful with
+    /// --retries.
+    #[arg(long = "retry-delay", global = true, default_value = "1s")]
+    retry_delay: String,
     #[command(subcommand)]
     command: Commands,
 }
@@ -33,59 +79,1199 @@ enum Commands {
     /// Request an one-off system-wide trace.
     Trace(TraceArgs),
     /// Convert traces to perf profiles.
-    Process,
+    Process(ProcessArgs),
     /// Create a report containing all profiles.
-    Report,
+    Report(ReportArgs),
     /// Clear all local data and reset the state.
-    Reset,
+    Reset(ResetArgs),
     /// Set property for profcollectd.
-    SetProperty,
+    SetProperty(SetPropertyArgs),
+    /// Bundle raw traces and a fresh report into a single directory.
+    Export(ExportArgs),
+    /// List the distinct tags present across stored traces.
+    Tags(TagsArgs),
+    /// Print new traces as they land in the trace directory, until interrupted.
+    Watch(WatchArgs),
+    /// Copy externally captured traces into the daemon-managed store.
+    Import(ImportArgs),
+    /// Save, run, list or delete named command presets.
+    Profile(ProfileArgs),
+    /// Restart the profcollectd daemon via init.
+    DaemonRestart(DaemonRestartArgs),
+    /// Generate a synthetic trace for development on machines without perf counters.
+    #[command(hide = true)]
+    Simulate(SimulateArgs),
+    /// Estimate the runtime overhead of tracing on this device.
+    Bench(BenchArgs),
 }
 
 #[derive(Args)]
-struct TraceArgs {
-    #[arg(short = 't', long = "tag", default_value_t = String::from("manual"))]
+struct ProfileArgs {
+    #[command(subcommand)]
+    action: ProfileAction,
+}
+
+#[derive(Subcommand)]
+enum ProfileAction {
+    /// Save a command line under a name, e.g. `profile save fast-boot trace --tag boot`.
+    Save {
+        /// Name to store the preset under; an existing preset is overwritten.
+        name: String,
+        /// The profcollectctl arguments to save.
+        #[arg(trailing_var_arg = true, required = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Run a saved preset, appending any extra arguments to the saved command line.
+    Run {
+        /// Name of the preset to run.
+        name: String,
+        /// Additional arguments appended to the saved ones.
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        extra: Vec<String>,
+    },
+    /// List the saved presets and their command lines.
+    List,
+    /// Delete a saved preset.
+    Delete {
+        /// Name of the preset to delete.
+        name: String,
+    },
+}
+
+/// File holding the named command presets, one per line as tab-separated
+/// `name<TAB>arg<TAB>arg...`.
+const PRESETS_FILE: &str = "/data/misc/profcollectd/presets.list";
+
+/// Loads the saved presets; a missing file means no presets.
+fn load_presets() -> Result<Vec<(String, Vec<String>)>> {
+    let text = match std::fs::read_to_string(PRESETS_FILE) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}.", PRESETS_FILE));
+        }
+    };
+    let mut presets = Vec::new();
+    for line in text.lines().filter(|line| !line.is_empty()) {
+        let mut fields = line.split('\t').map(str::to_string);
+        let name = fields.next().context("Malformed preset file.")?;
+        presets.push((name, fields.collect()));
+    }
+    Ok(presets)
+}
+
+/// Writes the presets back to the preset file.
+fn store_presets(presets: &[(String, Vec<String>)]) -> Result<()> {
+    let mut text = String::new();
+    for (name, command) in presets {
+        text.push_str(name);
+        for arg in command {
+            text.push('\t');
+            text.push_str(arg);
+        }
+        text.push('\n');
+    }
+    std::fs::write(PRESETS_FILE, text)
+        .with_context(|| format!("Failed to write {}.", PRESETS_FILE))
+}
+
+/// Builds the effective invocation for `profile run`: the saved arguments followed by any
+/// extra arguments, reparsed as a fresh command line.
+fn resolve_preset(name: &str, extra: &[String]) -> Result<Cli> {
+    let presets = load_presets()?;
+    let (_, saved) = presets
+        .iter()
+        .find(|(preset, _)| preset == name)
+        .with_context(|| format!("No preset named '{}'.", name))?;
+    let mut argv = vec![String::from("profcollectctl")];
+    argv.extend(saved.iter().cloned());
+    argv.extend(extra.iter().cloned());
+    let resolved = Cli::try_parse_from(&argv)
+        .with_context(|| format!("Preset '{}' does not form a valid command line.", name))?;
+    // A hand-edited preset file could otherwise chain presets into a loop.
+    anyhow::ensure!(
+        !matches!(
+            resolved.command,
+            Commands::Profile(ProfileArgs { action: ProfileAction::Run { .. } })
+        ),
+        "Preset '{}' may not run another preset.",
+        name
+    );
+    Ok(resolved)
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// Output format.
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Args)]
+struct SimulateArgs {
+    /// Tag to store the synthetic trace under.
+    #[arg(short = 't', long = "tag", default_value_t = String::from("synthetic"))]
+    tag: String,
+}
+
+#[derive(Args)]
+struct DaemonRestartArgs {
+    /// Wait for the service to come back up before returning.
+    #[arg(long = "wait")]
+    wait: bool,
+}
+
+/// Name of the profcollectd service as registered with init.
+const PROFCOLLECTD_SERVICE: &str = "profcollectd";
+/// How long `daemon-restart --wait` waits for the service to return.
+const RESTART_WAIT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Blocks until init reports the service as running, or the timeout expires.
+fn wait_for_service_running(service: &str) -> Result<()> {
+    let state_property = format!("init.svc.{}", service);
+    let deadline = std::time::Instant::now() + RESTART_WAIT_TIMEOUT;
+    while std::time::Instant::now() < deadline {
+        if system_properties::read(&state_property)?.as_deref() == Some("running") {
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    anyhow::bail!(
+        "Service {} did not return to running within {}s.",
+        service,
+        RESTART_WAIT_TIMEOUT.as_secs()
+    );
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+struct WatchArgs {
+    /// Only print traces stored under this tag.
+    #[arg(short = 't', long = "tag")]
+    tag: Option<String>,
+    /// Output format; `json` streams one object per line (NDJSON).
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    /// Seconds between polls of the trace directory.
+    #[arg(long = "interval", default_value_t = 1)]
+    interval: u64,
+}
+
+#[derive(Args)]
+struct ImportArgs {
+    /// Trace file, or directory of trace files, to import.
+    path: std::path::PathBuf,
+    /// Tag to store the imported traces under.
+    #[arg(short = 't', long = "tag", default_value_t = String::from("imported"))]
     tag: String,
-    #[arg(short = 'd', long = "duration", default_value_t = 1000)]
-    duration_ms: i32,
+    /// Import even if a trace with the same file name is already in the store.
+    #[arg(long = "force")]
+    force: bool,
+}
+
+/// File extensions the processing pipeline understands; anything else is rejected before
+/// it can pollute the store.
+const SUPPORTED_TRACE_EXTENSIONS: &[&str] = &["data", "etmtrace", "trace"];
+
+/// Checks that a file plausibly is a supported trace before it enters the store.
+fn validate_trace_file(path: &std::path::Path) -> Result<()> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    anyhow::ensure!(
+        SUPPORTED_TRACE_EXTENSIONS.contains(&extension),
+        "{} does not look like a supported trace (expected one of: {}).",
+        path.display(),
+        SUPPORTED_TRACE_EXTENSIONS.join(", ")
+    );
+    let metadata = std::fs::metadata(path)
+        .with_context(|| format!("Failed to read {}.", path.display()))?;
+    anyhow::ensure!(metadata.is_file(), "{} is not a file.", path.display());
+    anyhow::ensure!(metadata.len() > 0, "{} is empty.", path.display());
+    Ok(())
+}
+
+/// Extracts the tag from a trace file name of the form "<timestamp>_<tag>.<ext>".
+fn trace_file_tag(file_name: &std::ffi::OsStr) -> Option<String> {
+    let stem = std::path::Path::new(file_name).file_stem()?.to_str()?;
+    stem.split_once('_').map(|(_, tag)| tag.to_string())
+}
+
+#[derive(Args)]
+struct TagsArgs {
+    /// Output format.
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// Destination directory for the bundle.
+    dest: std::path::PathBuf,
+    /// Overwrite the destination if it already exists.
+    #[arg(long = "force")]
+    force: bool,
+}
+
+#[derive(Args)]
+struct SetPropertyArgs {
+    /// Property namespace to write the enabled state to.
+    #[arg(long = "namespace", default_value_t = String::from(DEFAULT_PROPERTY_NAMESPACE))]
+    namespace: String,
+    /// Server configurable flag namespace to read the enabled state from.
+    #[arg(long = "flag-namespace", default_value_t = String::from(DEFAULT_FLAG_NAMESPACE))]
+    flag_namespace: String,
+    /// List the known settable properties with their current values and allowed ranges,
+    /// without changing anything.
+    #[arg(long = "list")]
+    list: bool,
+    /// Output format for --list.
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+/// The settable profcollectd properties: name within the property namespace, allowed
+/// values, and what the property controls.
+const SETTABLE_PROPERTIES: &[(&str, &str, &str)] = &[
+    ("enabled", "true|false", "whether periodic collection runs"),
+    ("collection_interval", "seconds > 0", "time between periodic collections"),
+    ("sampling_period", "milliseconds > 0", "duration of each periodic trace"),
+    ("binary_filter", "regex", "binaries whose samples are kept"),
+    ("max_trace_limit", "count >= 0", "stored traces before collection pauses"),
+];
+
+#[derive(Args)]
+struct ProcessArgs {
+    /// Only process traces collected after the current boot.
+    #[arg(long = "since-boot")]
+    since_boot: bool,
+    /// Keep the raw traces after conversion, so they can be reprocessed later.
+    #[arg(long = "keep-traces", conflicts_with = "delete_traces")]
+    keep_traces: bool,
+    /// Delete the raw traces after conversion to reclaim space. This is the
+    /// default behavior, made explicit.
+    #[arg(long = "delete-traces")]
+    delete_traces: bool,
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    /// Only include profiles from traces collected after the current boot.
+    #[arg(long = "since-boot")]
+    since_boot: bool,
+    /// Keep the report under this many bytes by dropping lowest-priority profiles.
+    #[arg(long = "max-size")]
+    max_size: Option<u64>,
+    /// Only include profiles collected after this time (a relative age like "2h", or an
+    /// absolute Unix timestamp in seconds).
+    #[arg(long = "since", conflicts_with = "since_boot")]
+    since: Option<String>,
+    /// Only include profiles collected before this time (same formats as --since).
+    #[arg(long = "until", conflicts_with = "since_boot")]
+    until: Option<String>,
+    /// Leave device and build metadata out of the report, for privacy-sensitive contexts.
+    #[arg(long = "no-metadata")]
+    no_metadata: bool,
+    /// Compress the produced report, appending the matching extension to its name.
+    #[arg(long = "compress", value_enum, default_value_t = CompressionAlgo::None)]
+    compress: CompressionAlgo,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum CompressionAlgo {
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// Applies the requested `--compress` choice to a produced report, returning the
+/// final path.
+fn compress_report(path: String, algo: CompressionAlgo) -> Result<String> {
+    match algo {
+        CompressionAlgo::None => Ok(path),
+        CompressionAlgo::Gzip => local::compress_report_gzip(&path),
+        // Rejected before the report is built; kept here for completeness.
+        CompressionAlgo::Zstd => anyhow::bail!("zstd support is not compiled in."),
+    }
+}
+
+/// Prints the final report location and size.
+fn print_report_path(path: &str) {
+    match std::fs::metadata(path) {
+        Ok(metadata) => println!("Report created at: {} ({} bytes)", path, metadata.len()),
+        Err(_) => println!("Report created at: {}", path),
+    }
+}
+
+/// Collects the device and build metadata stamped into reports, so aggregated reports
+/// remain attributable to their source device and build.
+fn gather_report_metadata() -> Result<local::ReportMetadata> {
+    let read_property = |name: &str| -> String {
+        system_properties::read(name)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| String::from("unknown"))
+    };
+    let kernel_version = std::fs::read_to_string("/proc/version")
+        .map(|version| version.trim().to_string())
+        .unwrap_or_else(|_| String::from("unknown"));
+    Ok(local::ReportMetadata {
+        device_id: read_property("ro.serialno"),
+        build_fingerprint: read_property("ro.build.fingerprint"),
+        abi: read_property("ro.product.cpu.abi"),
+        kernel_version,
+    })
+}
+
+/// Parses a report window bound: a relative age like "2h" (that long before now) or an
+/// absolute Unix timestamp in seconds.
+fn parse_time(text: &str) -> Result<std::time::SystemTime> {
+    if let Ok(epoch_secs) = text.parse::<u64>() {
+        return Ok(std::time::UNIX_EPOCH + std::time::Duration::from_secs(epoch_secs));
+    }
+    Ok(std::time::SystemTime::now() - parse_duration(text)?)
+}
+
+#[derive(Args)]
+struct ResetArgs {
+    /// Only remove traces and profiles older than the given duration (e.g. "7d", "12h",
+    /// "30m", or plain seconds).
+    #[arg(long = "older-than")]
+    older_than: Option<String>,
+    /// Skip the confirmation prompt.
+    #[arg(short = 'y', long = "yes")]
+    yes: bool,
+}
+
+/// Parses a retention duration of the form "7d", "12h", "30m", "90s" or plain seconds.
+fn parse_duration(text: &str) -> Result<std::time::Duration> {
+    let seconds = if let Ok(seconds) = text.parse::<u64>() {
+        seconds
+    } else {
+        let (value, unit) = text.split_at(text.len().saturating_sub(1));
+        let value: u64 = value.parse().context("Failed to parse duration value.")?;
+        match unit {
+            "d" => value * 24 * 60 * 60,
+            "h" => value * 60 * 60,
+            "m" => value * 60,
+            "s" => value,
+            unknown => anyhow::bail!("Unknown duration unit: {}", unknown),
+        }
+    };
+    Ok(std::time::Duration::from_secs(seconds))
+}
+
+/// Asks the user to confirm a destructive action.
+fn confirm(prompt: &str) -> Result<()> {
+    use std::io::Write;
+
+    print!("{} [y/N] ", prompt);
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    anyhow::ensure!(matches!(line.trim(), "y" | "Y" | "yes"), "Aborted.");
+    Ok(())
+}
+
+#[derive(Args)]
+struct TraceArgs {
+    /// Tag to store the trace under; defaults to "manual".
+    #[arg(short = 't', long = "tag")]
+    tag: Option<String>,
+    /// Trace duration in milliseconds; defaults to 1000.
+    #[arg(short = 'd', long = "duration")]
+    duration_ms: Option<i32>,
+    /// Keep collecting until the daemon observes the named system event (e.g. a
+    /// boot-complete broadcast) or a safety timeout, instead of a fixed duration.
+    #[arg(long = "until-event", conflicts_with = "duration_ms")]
+    until_event: Option<String>,
+    /// Free-form note stored alongside the produced trace.
+    #[arg(long = "annotate")]
+    annotate: Option<String>,
+    /// File providing default values for the trace parameters, which the flags above
+    /// override. Lines have the form `tag = "manual"` or `duration = 1000`.
+    #[arg(long = "config-file")]
+    config_file: Option<std::path::PathBuf>,
+    /// Write the capture to this directory instead of the daemon default, creating it if
+    /// needed.
+    #[arg(long = "output-dir")]
+    output_dir: Option<std::path::PathBuf>,
+}
+
+/// Safety timeout in milliseconds for `--until-event` captures whose event never occurs.
+const UNTIL_EVENT_TIMEOUT_MS: i32 = 60_000;
+
+/// Ensures the given trace output directory exists and is writable.
+fn prepare_output_dir(dir: &std::path::Path) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create output directory {}.", dir.display()))?;
+    // Probe writability up front so the failure surfaces before the trace runs.
+    let probe = dir.join(".profcollectctl-write-probe");
+    std::fs::write(&probe, b"")
+        .with_context(|| format!("Output directory {} is not writable.", dir.display()))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Trace parameter defaults loaded from a `--config-file`.
+#[derive(Default)]
+struct TraceConfig {
+    tag: Option<String>,
+    duration_ms: Option<i32>,
+    annotate: Option<String>,
+}
+
+/// Parses a trace config file of simple `key = value` lines (a TOML subset).
+///
+/// Recognized keys are `tag`, `duration` and `annotate`; string values may be quoted.
+/// Unknown keys and malformed values are hard errors so a typo cannot silently change
+/// what gets profiled.
+fn load_trace_config(path: &std::path::Path) -> Result<TraceConfig> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}.", path.display()))?;
+    let mut config = TraceConfig::default();
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("Malformed line {} in config file.", line_number + 1))?;
+        let value = value.trim().trim_matches('"');
+        match key.trim() {
+            "tag" => config.tag = Some(value.to_string()),
+            "duration" => {
+                config.duration_ms =
+                    Some(value.parse().with_context(|| {
+                        format!("Invalid duration on line {}.", line_number + 1)
+                    })?)
+            }
+            "annotate" => config.annotate = Some(value.to_string()),
+            unknown => anyhow::bail!(
+                "Unknown key '{}' on line {} in config file.",
+                unknown,
+                line_number + 1
+            ),
+        }
+    }
+    Ok(config)
+}
+
+/// Longest annotation stored with a trace.
+const MAX_ANNOTATION_LEN: usize = 256;
+
+/// Normalizes a trace annotation: newlines become spaces and overlong notes are rejected.
+fn sanitize_note(note: &str) -> Result<String> {
+    anyhow::ensure!(
+        note.len() <= MAX_ANNOTATION_LEN,
+        "Annotation exceeds {} bytes.",
+        MAX_ANNOTATION_LEN
+    );
+    Ok(note.replace(['\n', '\r'], " "))
+}
+
+/// Returns the time the system booted, derived from the current time and `/proc/uptime`.
+fn boot_time() -> Result<std::time::SystemTime> {
+    let uptime = std::fs::read_to_string("/proc/uptime").context("Failed to read uptime.")?;
+    let uptime_secs: f64 = uptime
+        .split_whitespace()
+        .next()
+        .and_then(|s| s.parse().ok())
+        .context("Failed to parse uptime.")?;
+    Ok(std::time::SystemTime::now() - std::time::Duration::from_secs_f64(uptime_secs))
+}
+
+/// JSON Schema for the structured outputs, keyed by subcommand. Kept by hand next to the
+/// `--format json` printers; update both together so the contract stays accurate.
+const JSON_SCHEMA: &str = r#"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "title": "profcollectctl JSON outputs",
+  "properties": {
+    "tags": {
+      "type": "array",
+      "items": {
+        "type": "object",
+        "properties": {"tag": {"type": "string"}, "count": {"type": "integer"}},
+        "required": ["tag", "count"]
+      }
+    },
+    "watch": {
+      "description": "One object per line (NDJSON)",
+      "type": "object",
+      "properties": {
+        "name": {"type": "string"},
+        "tag": {"type": "string"},
+        "size": {"type": "integer"}
+      },
+      "required": ["name", "tag", "size"]
+    },
+    "bench": {
+      "type": "object",
+      "properties": {
+        "baseline_ms": {"type": "number"},
+        "traced_ms": {"type": "number"},
+        "overhead_ms": {"type": "number"},
+        "overhead_percent": {"type": "number"},
+        "estimate": {"type": "boolean"}
+      },
+      "required": ["baseline_ms", "traced_ms", "overhead_ms", "overhead_percent", "estimate"]
+    },
+    "set-property-list": {
+      "type": "array",
+      "items": {
+        "type": "object",
+        "properties": {
+          "name": {"type": "string"},
+          "value": {"type": "string"},
+          "allowed": {"type": "string"},
+          "description": {"type": "string"}
+        },
+        "required": ["name", "value", "allowed", "description"]
+      }
+    }
+  }
+}"#;
+
+/// Escapes a string for inclusion in a JSON string literal, so file names and property
+/// values cannot break the `--format json` output.
+fn json_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Error message fragments that indicate a transient daemon condition worth retrying.
+/// Anything else (bad arguments, empty data sets, user aborts) fails immediately.
+const TRANSIENT_ERROR_MARKERS: &[&str] =
+    &["busy", "unavailable", "connection", "timed out", "try again"];
+
+/// Returns true if any error in the chain looks like a transient daemon failure.
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        let text = cause.to_string().to_lowercase();
+        TRANSIENT_ERROR_MARKERS.iter().any(|marker| text.contains(marker))
+    })
 }
 
 fn main() -> Result<()> {
-    libprofcollectd::init_logging();
+    let mut cli = Cli::parse();
+    if cli.json_schema {
+        println!("{}", JSON_SCHEMA);
+        return Ok(());
+    }
+    // Presets are resolved into an ordinary invocation before dispatch, so the resolved
+    // command goes through the same logging, gating and retry paths as a typed-out one.
+    if let Commands::Profile(ProfileArgs { action: ProfileAction::Run { name, extra } }) =
+        &cli.command
+    {
+        cli = resolve_preset(name, extra)?;
+    }
+    match &cli.log_to {
+        Some(path) => local::init_logging_to_file(path)
+            .with_context(|| format!("Failed to log to {}.", path.display()))?,
+        None => libprofcollectd::init_logging(),
+    }
+
+    let retry_delay = parse_duration(&cli.retry_delay).context("Invalid --retry-delay.")?;
+    let mut attempts = 0;
+    loop {
+        match run_command(&cli) {
+            Ok(()) => return Ok(()),
+            Err(error) if attempts < cli.retries && is_transient(&error) => {
+                attempts += 1;
+                log::debug!(
+                    "Transient failure, retrying ({}/{}): {:#}",
+                    attempts,
+                    cli.retries,
+                    error
+                );
+                std::thread::sleep(retry_delay);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Directory scanned by commands that read traces: the `--input-dir` override in offline
+/// mode, the on-device store otherwise.
+fn trace_dir(cli: &Cli) -> std::path::PathBuf {
+    cli.input_dir
+        .clone()
+        .unwrap_or_else(|| PROFCOLLECTD_DATA_DIRS[0].into())
+}
 
-    let cli = Cli::parse();
+/// Executes the selected subcommand once.
+fn run_command(cli: &Cli) -> Result<()> {
+    if cli.no_daemon {
+        match &cli.command {
+            // Read-only commands that scan the data directory work offline, as does
+            // preset bookkeeping.
+            Commands::Tags(_) | Commands::Watch(_) | Commands::Profile(_) => {}
+            Commands::Report(_) => {
+                anyhow::ensure!(
+                    cli.input_dir.is_some(),
+                    "report with --no-daemon requires --input-dir."
+                );
+            }
+            _ => anyhow::bail!("This command requires the profcollectd daemon."),
+        }
+    }
     match &cli.command {
-        Commands::Trace(TraceArgs { tag, duration_ms }) => {
+        Commands::Trace(TraceArgs {
+            tag,
+            duration_ms,
+            until_event,
+            annotate,
+            config_file,
+            output_dir,
+        }) => {
+            // Command-line flags override the config file, which overrides the defaults.
+            let config = config_file
+                .as_deref()
+                .map(load_trace_config)
+                .transpose()?
+                .unwrap_or_default();
+            let tag = tag
+                .clone()
+                .or(config.tag)
+                .unwrap_or_else(|| String::from("manual"));
+            let duration_ms = duration_ms.or(config.duration_ms).unwrap_or(1000);
+            let annotate = annotate.clone().or(config.annotate);
+            let note = annotate.as_deref().map(sanitize_note).transpose()?;
+            if cli.dry_run {
+                if let Some(event) = until_event {
+                    println!(
+                        "Dry run: would perform a system-wide trace until event '{}' with tag '{}'",
+                        event, tag
+                    );
+                } else {
+                    println!(
+                        "Dry run: would perform a system-wide trace for {}ms with tag '{}'",
+                        duration_ms, tag
+                    );
+                }
+                return Ok(());
+            }
             println!("Performing system-wide trace");
-            libprofcollectd::trace_system(tag, *duration_ms).context("Failed to trace.")?;
+            let options = local::TraceOptions { tag: tag.clone(), duration_ms, note };
+            if let Some(event) = until_event {
+                anyhow::ensure!(
+                    output_dir.is_none(),
+                    "--until-event cannot be combined with --output-dir."
+                );
+                local::trace_until(options, event, UNTIL_EVENT_TIMEOUT_MS)
+                    .context("Failed to trace.")?;
+            } else if let Some(dir) = output_dir {
+                prepare_output_dir(dir)?;
+                local::trace_to_dir(dir, options).context("Failed to trace.")?;
+            } else if options.note.is_none() {
+                libprofcollectd::trace_system(&tag, duration_ms).context("Failed to trace.")?;
+            } else {
+                local::trace_with_options(options).context("Failed to trace.")?;
+            }
         }
-        Commands::Process => {
+        Commands::Process(ProcessArgs { since_boot, keep_traces, delete_traces: _ }) => {
+            let since = if *since_boot { Some(boot_time()?) } else { None };
+            if cli.dry_run {
+                println!("Dry run: would process all traces");
+                return Ok(());
+            }
             println!("Processing traces");
-            libprofcollectd::process().context("Failed to process traces.")?;
+            let (converted, failed) = local::process_with_options(local::ProcessOptions {
+                since,
+                keep_traces: *keep_traces,
+            })
+            .context("Failed to process traces.")?;
+            if *since_boot && converted == 0 && failed == 0 {
+                anyhow::bail!("No traces found from the current boot.");
+            }
+            println!("Converted {} traces, {} failed.", converted, failed);
+            if *keep_traces {
+                println!("Kept {} raw traces.", converted + failed);
+            } else {
+                println!("Removed {} raw traces.", converted);
+            }
         }
-        Commands::Report => {
+        Commands::Report(ReportArgs {
+            since_boot,
+            max_size,
+            since,
+            until,
+            no_metadata,
+            compress,
+        }) => {
+            anyhow::ensure!(
+                !matches!(compress, CompressionAlgo::Zstd),
+                "zstd support is not compiled in; use --compress gzip."
+            );
+            if cli.no_daemon {
+                anyhow::ensure!(
+                    matches!(compress, CompressionAlgo::None),
+                    "--compress is not supported for offline reports."
+                );
+                // Offline mode: build the report straight from the pulled data directory.
+                // Device metadata is unavailable off-device, so none is stamped in.
+                let input_dir = trace_dir(cli);
+                if cli.dry_run {
+                    println!(
+                        "Dry run: would create a profile report from {}",
+                        input_dir.display()
+                    );
+                    return Ok(());
+                }
+                let path = local::report_offline(&input_dir)
+                    .context("Failed to create profile report.")?;
+                println!("Report created at: {}", &path);
+                return Ok(());
+            }
+            let metadata = if *no_metadata {
+                None
+            } else {
+                Some(gather_report_metadata()?)
+            };
+            if cli.dry_run {
+                println!("Dry run: would create a profile report from processed profiles");
+                return Ok(());
+            }
             println!("Creating profile report");
-            let path = libprofcollectd::report().context("Failed to create profile report.")?;
-            println!("Report created at: {}", &path);
+            if since.is_some() || until.is_some() || *since_boot {
+                // `--since-boot` is shorthand for a window opening at boot time.
+                let start = if *since_boot {
+                    boot_time()?
+                } else {
+                    since
+                        .as_deref()
+                        .map(parse_time)
+                        .transpose()?
+                        .unwrap_or(std::time::UNIX_EPOCH)
+                };
+                let end = until
+                    .as_deref()
+                    .map(parse_time)
+                    .transpose()?
+                    .unwrap_or_else(std::time::SystemTime::now);
+                anyhow::ensure!(start <= end, "--since must not be later than --until.");
+                let path = local::report_window(start, end, metadata)
+                    .context("Failed to create profile report.")?;
+                match path {
+                    Some(path) => {
+                        let path = compress_report(path, *compress)?;
+                        print_report_path(&path);
+                        return Ok(());
+                    }
+                    None => anyhow::bail!("No profiles found in the requested window."),
+                }
+            }
+            if let Some(max_bytes) = max_size {
+                let (path, included, omitted) = local::report_bounded(*max_bytes, metadata)
+                    .with_context(|| {
+                        format!("Failed to create a report within {} bytes.", max_bytes)
+                    })?;
+                let path = compress_report(path, *compress)?;
+                println!(
+                    "{} profiles included, {} omitted to stay under {} bytes.",
+                    included, omitted, max_bytes
+                );
+                print_report_path(&path);
+                return Ok(());
+            }
+            let path = match metadata {
+                Some(metadata) => local::report_with_metadata(metadata)
+                    .context("Failed to create profile report.")?,
+                None => libprofcollectd::report().context("Failed to create profile report.")?,
+            };
+            let path = compress_report(path, *compress)?;
+            print_report_path(&path);
         }
-        Commands::Reset => {
-            libprofcollectd::reset().context("Failed to reset.")?;
-            println!("Reset done.");
+        Commands::Reset(ResetArgs { older_than, yes }) => {
+            let age = older_than.as_deref().map(parse_duration).transpose()?;
+            if cli.dry_run {
+                // The listing applies the same age filter as the real run, so it previews
+                // exactly what that run would remove.
+                let cutoff = age.map(|age| std::time::SystemTime::now() - age);
+                println!("Dry run: would remove the following files:");
+                for dir in PROFCOLLECTD_DATA_DIRS {
+                    // The directories may not all exist, e.g. before the first collection.
+                    let Ok(entries) = std::fs::read_dir(dir) else {
+                        continue;
+                    };
+                    for entry in entries.flatten() {
+                        if let Some(cutoff) = cutoff {
+                            let modified = entry.metadata().and_then(|m| m.modified());
+                            if !modified.is_ok_and(|modified| modified < cutoff) {
+                                continue;
+                            }
+                        }
+                        println!("  {}", entry.path().display());
+                    }
+                }
+                return Ok(());
+            }
+            match age {
+                Some(age) => {
+                    if !yes {
+                        confirm(&format!(
+                            "This will delete traces and profiles older than {}, continue?",
+                            older_than.as_deref().unwrap_or_default()
+                        ))?;
+                    }
+                    let cutoff = std::time::SystemTime::now() - age;
+                    let (files, bytes) = local::prune(cutoff).context("Failed to prune.")?;
+                    println!("Removed {} files ({} bytes).", files, bytes);
+                }
+                None => {
+                    if !yes {
+                        confirm("This will delete all local data, continue?")?;
+                    }
+                    libprofcollectd::reset().context("Failed to reset.")?;
+                    println!("Reset done.");
+                }
+            }
         }
-        Commands::SetProperty => {
-            let old_value = system_properties::read("persist.profcollectd.enabled")?
-                .unwrap_or("false".to_string());
-            let new_value =
-                match GetServerConfigurableFlag("profcollect_native_boot", "enabled", "false")
-                    .as_str()
+        Commands::Export(ExportArgs { dest, force }) => {
+            if dest.exists() && !force {
+                anyhow::bail!(
+                    "Destination {} already exists, pass --force to overwrite.",
+                    dest.display()
+                );
+            }
+            if cli.dry_run {
+                println!(
+                    "Dry run: would process traces, build a report, and export the bundle to {}",
+                    dest.display()
+                );
+                return Ok(());
+            }
+            println!("Processing traces");
+            libprofcollectd::process().context("Failed to process traces.")?;
+            println!("Creating profile report");
+            let report_path =
+                libprofcollectd::report().context("Failed to create profile report.")?;
+            local::bundle_export(&report_path, dest)
+                .context("Failed to write export bundle.")?;
+            println!("Export written to: {}", dest.display());
+        }
+        Commands::Tags(TagsArgs { format }) => {
+            // Trace files are named "<timestamp>_<tag>.<ext>", so the tag can be read off
+            // the file name without opening the trace.
+            let mut counts = std::collections::BTreeMap::<String, usize>::new();
+            if let Ok(entries) = std::fs::read_dir(trace_dir(cli)) {
+                for entry in entries.flatten() {
+                    if let Some(tag) = trace_file_tag(&entry.file_name()) {
+                        *counts.entry(tag).or_default() += 1;
+                    }
+                }
+            }
+            match format {
+                OutputFormat::Text => {
+                    if counts.is_empty() {
+                        println!("No traces found.");
+                    }
+                    for (tag, count) in &counts {
+                        println!("{}: {}", tag, count);
+                    }
+                }
+                OutputFormat::Json => {
+                    let objects: Vec<String> = counts
+                        .iter()
+                        .map(|(tag, count)| {
+                            format!("{{\"tag\":\"{}\",\"count\":{}}}", json_escape(tag), count)
+                        })
+                        .collect();
+                    println!("[{}]", objects.join(","));
+                }
+            }
+        }
+        Commands::Profile(ProfileArgs { action }) => match action {
+            ProfileAction::Save { name, command } => {
+                anyhow::ensure!(
+                    !name.is_empty() && !name.contains(['\t', '\n']),
+                    "Preset names cannot be empty or contain tabs or newlines."
+                );
+                anyhow::ensure!(
+                    command.iter().all(|arg| !arg.contains(['\t', '\n'])),
+                    "Preset arguments cannot contain tabs or newlines."
+                );
+                anyhow::ensure!(
+                    command.first().map(String::as_str) != Some("profile"),
+                    "A preset cannot invoke the profile command itself."
+                );
+                // Reject command lines that wouldn't parse now rather than at run time.
+                let mut argv = vec![String::from("profcollectctl")];
+                argv.extend(command.iter().cloned());
+                Cli::try_parse_from(&argv)
+                    .context("The preset does not form a valid command line.")?;
+                if cli.dry_run {
+                    println!("Dry run: would save preset '{}'", name);
+                    return Ok(());
+                }
+                let mut presets = load_presets()?;
+                presets.retain(|(preset, _)| preset != name);
+                presets.push((name.clone(), command.clone()));
+                store_presets(&presets)?;
+                println!("Saved preset '{}'", name);
+            }
+            ProfileAction::List => {
+                for (name, command) in load_presets()? {
+                    println!("{}: {}", name, command.join(" "));
+                }
+            }
+            ProfileAction::Delete { name } => {
+                let mut presets = load_presets()?;
+                let count = presets.len();
+                presets.retain(|(preset, _)| preset != name);
+                anyhow::ensure!(presets.len() < count, "No preset named '{}'.", name);
+                if cli.dry_run {
+                    println!("Dry run: would delete preset '{}'", name);
+                    return Ok(());
+                }
+                store_presets(&presets)?;
+                println!("Deleted preset '{}'", name);
+            }
+            // Resolved into the saved command in `main` before dispatch.
+            ProfileAction::Run { .. } => unreachable!("preset runs are resolved in main"),
+        },
+        Commands::Import(ImportArgs { path, tag, force }) => {
+            // Expand a directory into its trace files; a plain file imports as-is.
+            let files: Vec<std::path::PathBuf> = if path.is_dir() {
+                let mut files = Vec::new();
+                for entry in std::fs::read_dir(path)
+                    .with_context(|| format!("Failed to read {}.", path.display()))?
                 {
+                    let entry_path = entry?.path();
+                    if entry_path.is_file() {
+                        files.push(entry_path);
+                    }
+                }
+                files
+            } else {
+                vec![path.clone()]
+            };
+            anyhow::ensure!(!files.is_empty(), "No files found at {}.", path.display());
+            for file in &files {
+                validate_trace_file(file)?;
+            }
+            if !force {
+                // The store names imported traces deterministically from the source file,
+                // so a name collision means the trace was already imported.
+                let existing: std::collections::HashSet<String> =
+                    match std::fs::read_dir(PROFCOLLECTD_DATA_DIRS[0]) {
+                        Ok(entries) => entries
+                            .flatten()
+                            .map(|e| e.file_name().to_string_lossy().into_owned())
+                            .collect(),
+                        Err(_) => Default::default(),
+                    };
+                for file in &files {
+                    let name = local::import_file_name(file, tag)?;
+                    anyhow::ensure!(
+                        !existing.contains(&name),
+                        "{} is already in the store, pass --force to import anyway.",
+                        name
+                    );
+                }
+            }
+            if cli.dry_run {
+                println!(
+                    "Dry run: would import {} trace(s) under tag '{}'",
+                    files.len(),
+                    tag
+                );
+                return Ok(());
+            }
+            for file in &files {
+                local::import_trace(file, tag)
+                    .with_context(|| format!("Failed to import {}.", file.display()))?;
+            }
+            println!("Imported {} trace(s) under tag '{}'.", files.len(), tag);
+        }
+        Commands::Watch(WatchArgs {
+            tag,
+            format,
+            interval,
+        }) => {
+            if cli.dry_run {
+                println!("Dry run: would watch the trace directory for new traces");
+                return Ok(());
+            }
+            let trace_dir = trace_dir(cli);
+            // Traces already present when the watch starts are not "new"; seed the seen set
+            // with them so only traces landing afterwards are printed.
+            let mut seen = std::collections::HashSet::<std::ffi::OsString>::new();
+            if let Ok(entries) = std::fs::read_dir(&trace_dir) {
+                for entry in entries.flatten() {
+                    seen.insert(entry.file_name());
+                }
+            }
+            eprintln!("Watching {} for new traces, Ctrl-C to stop.", trace_dir.display());
+            loop {
+                if let Ok(entries) = std::fs::read_dir(&trace_dir) {
+                    for entry in entries.flatten() {
+                        let file_name = entry.file_name();
+                        if !seen.insert(file_name.clone()) {
+                            continue;
+                        }
+                        let trace_tag = trace_file_tag(&file_name).unwrap_or_default();
+                        if tag.as_deref().is_some_and(|wanted| wanted != trace_tag) {
+                            continue;
+                        }
+                        let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                        let name = file_name.to_string_lossy();
+                        match format {
+                            OutputFormat::Text => {
+                                println!("{} tag={} size={}", name, trace_tag, size)
+                            }
+                            OutputFormat::Json => println!(
+                                "{{\"name\":\"{}\",\"tag\":\"{}\",\"size\":{}}}",
+                                json_escape(&name),
+                                json_escape(&trace_tag),
+                                size
+                            ),
+                        }
+                    }
+                }
+                std::thread::sleep(std::time::Duration::from_secs((*interval).max(1)));
+            }
+        }
+        Commands::Bench(BenchArgs { format }) => {
+            if cli.dry_run {
+                println!("Dry run: would measure tracing overhead");
+                return Ok(());
+            }
+            println!("Measuring tracing overhead, this runs a workload twice");
+            let overhead =
+                local::measure_overhead().context("Failed to measure tracing overhead.")?;
+            let delta_ms = overhead.traced_ms - overhead.baseline_ms;
+            let percent = delta_ms / overhead.baseline_ms * 100.0;
+            match format {
+                OutputFormat::Text => {
+                    println!("Baseline workload: {:.1} ms", overhead.baseline_ms);
+                    println!("Traced workload:   {:.1} ms", overhead.traced_ms);
+                    println!(
+                        "Estimated overhead: {:.1} ms ({:.1}%). Results are estimates and \
+                         vary with system load.",
+                        delta_ms, percent
+                    );
+                }
+                OutputFormat::Json => {
+                    println!(
+                        "{{\"baseline_ms\":{:.1},\"traced_ms\":{:.1},\"overhead_ms\":{:.1},\
+                         \"overhead_percent\":{:.1},\"estimate\":true}}",
+                        overhead.baseline_ms, overhead.traced_ms, delta_ms, percent
+                    );
+                }
+            }
+        }
+        Commands::Simulate(SimulateArgs { tag }) => {
+            if cli.dry_run {
+                println!("Dry run: would generate a synthetic trace with tag '{}'", tag);
+                return Ok(());
+            }
+            println!("Generating synthetic trace");
+            local::generate_synthetic(tag).context("Failed to generate synthetic trace.")?;
+            // Make the provenance unmissable so a synthetic trace is never mistaken for a
+            // real profile downstream.
+            println!("SYNTHETIC data generated under tag '{}'; not real profiling data.", tag);
+        }
+        Commands::DaemonRestart(DaemonRestartArgs { wait }) => {
+            if cli.dry_run {
+                println!("Dry run: would restart {} via ctl.restart", PROFCOLLECTD_SERVICE);
+                return Ok(());
+            }
+            println!("Restarting {}", PROFCOLLECTD_SERVICE);
+            system_properties::write("ctl.restart", PROFCOLLECTD_SERVICE)
+                .context("Failed to request daemon restart.")?;
+            if *wait {
+                wait_for_service_running(PROFCOLLECTD_SERVICE)?;
+                println!("Service {} is running.", PROFCOLLECTD_SERVICE);
+            }
+        }
+        Commands::SetProperty(SetPropertyArgs {
+            namespace,
+            flag_namespace,
+            list,
+            format,
+        }) => {
+            if !ALLOWED_PROPERTY_NAMESPACES.contains(&namespace.as_str()) {
+                anyhow::bail!("Property namespace not allowed: {}", &namespace);
+            }
+            if *list {
+                let current = |name: &str| -> String {
+                    system_properties::read(&format!("{}.{}", namespace, name))
+                        .ok()
+                        .flatten()
+                        .unwrap_or_else(|| String::from("unset"))
+                };
+                match format {
+                    OutputFormat::Text => {
+                        for (name, allowed, description) in SETTABLE_PROPERTIES {
+                            println!(
+                                "{}.{} = {} (allowed: {}; {})",
+                                namespace,
+                                name,
+                                current(name),
+                                allowed,
+                                description
+                            );
+                        }
+                    }
+                    OutputFormat::Json => {
+                        let objects: Vec<String> = SETTABLE_PROPERTIES
+                            .iter()
+                            .map(|(name, allowed, description)| {
+                                format!(
+                                    "{{\"name\":\"{}.{}\",\"value\":\"{}\",\
+                                     \"allowed\":\"{}\",\"description\":\"{}\"}}",
+                                    json_escape(namespace),
+                                    name,
+                                    json_escape(&current(name)),
+                                    json_escape(allowed),
+                                    description
+                                )
+                            })
+                            .collect();
+                        println!("[{}]", objects.join(","));
+                    }
+                }
+                return Ok(());
+            }
+            if !ALLOWED_FLAG_NAMESPACES.contains(&flag_namespace.as_str()) {
+                anyhow::bail!("Flag namespace not allowed: {}", &flag_namespace);
+            }
+
+            let property = format!("{}.{}", &namespace, ENABLED_NAME);
+            let old_value =
+                system_properties::read(&property)?.unwrap_or("false".to_string());
+            let new_value =
+                match GetServerConfigurableFlag(flag_namespace, ENABLED_NAME, "false").as_str() {
                     "1" | "y" | "yes" | "on" | "true" => "true",
                     "0" | "n" | "no" | "off" | "false" => "false",
                     invalid => anyhow::bail!("Failed to parse server flag as bool: {}", &invalid),
                 };
 
             if old_value != new_value {
-                system_properties::write("persist.profcollectd.enabled", new_value)?;
+                if cli.dry_run {
+                    println!(
+                        "Dry run: would set {} to {} (currently {})",
+                        &property, new_value, &old_value
+                    );
+                    return Ok(());
+                }
+                system_properties::write(&property, new_value)?;
             }
         }
     }